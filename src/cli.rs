@@ -0,0 +1,296 @@
+use crate::journal::Event;
+use crate::settings::Settings;
+use crate::table::{self, Format};
+use crate::theme::{self, Theme};
+use crate::{
+    Filter, SyncState, Task, TaskState, append_paragraph, crypto, find_task, git, html, ipc, lock,
+    profiles,
+};
+use chrono::Utc;
+use clap::Subcommand;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Unlocks an encrypted profile for commands, which cannot ask for it.
+const PASSPHRASE_VAR: &str = "CARDAMOM_CHAI_PASSPHRASE";
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Add a task, read like the add box: a trailing `!` or `*` makes it
+    /// chosen (the `*` is kept) and a trailing `?` uncertain
+    Add {
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+    /// Print the tasks, newest first, with their ids
+    List {
+        #[arg(long, value_enum, default_value = "all")]
+        filter: Filter,
+        /// Print a JSON array instead of a line per task
+        #[arg(long)]
+        json: bool,
+    },
+    /// Mark the task whose id starts with the given prefix as done
+    Done { id_prefix: String },
+    /// Print or add to the notes
+    Notes {
+        #[command(subcommand)]
+        command: NotesCommand,
+    },
+    /// Write the tasks and notes in another format
+    Export {
+        #[arg(long, value_enum)]
+        format: Format,
+        /// File to write instead of standard output
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NotesCommand {
+    /// Print the notes
+    Show,
+    /// Add a paragraph to the end of the notes, read from standard input
+    /// when no text is given
+    Append { text: Vec<String> },
+}
+
+/// Sends `request` to the running app, which would otherwise overwrite
+/// what the command writes. `None` when the app is not running.
+async fn forward(request: ipc::Request) -> Result<Option<ipc::Response>, String> {
+    let state = crate::sync_state_init();
+    match ipc::send(&state.base_path, &request).await? {
+        Some(response) if !response.ok => Err(response.error.unwrap_or_default()),
+        response => Ok(response),
+    }
+}
+
+/// The data of the profile the app would open, with the storage it would
+/// use. Commands that write take the single-instance lock first, so they
+/// never write under a running app that did not take their request.
+async fn open(write: bool) -> Result<(SyncState, Settings), String> {
+    let mut state = crate::sync_state_init();
+    let settings = state
+        .settings_store
+        .lock()
+        .unwrap()
+        .load_settings()?
+        .unwrap_or_default()
+        .with_overrides(&state.settings_overrides)?;
+
+    if write {
+        match lock::acquire(&state.base_path) {
+            Ok(file) => state.instance_lock = Some(file),
+            Err(lock::Status::HeldBy(Some(pid))) => {
+                return Err(format!(
+                    "another cardamom-chai (pid {pid}) is using this data directory"
+                ));
+            }
+            Err(_) => return Err("another cardamom-chai is using this data directory".into()),
+        }
+    }
+
+    if !profiles::is_valid_name(&settings.profile) {
+        return Err(format!("invalid profile name '{}'", settings.profile));
+    }
+    state.storage = settings.storage;
+    state.encrypted = settings.encryption;
+    state.todo_txt_path = settings.todo_txt_path.clone();
+    state.markdown_dir = settings.markdown_dir.clone();
+    state.set_profile_paths(&profiles::profile_dir(&state.base_path, &settings.profile));
+
+    if state.locked() {
+        let passphrase = std::env::var(PASSPHRASE_VAR)
+            .map_err(|_| format!("the profile is encrypted; set {PASSPHRASE_VAR} to unlock it"))?;
+        let dir = state.tasks_path.parent().unwrap_or(&state.base_path);
+        state.key = Some(crypto::unlock(dir, &passphrase)?);
+        state.open_storage();
+    }
+    state.storage_settled().await;
+    Ok((state, settings))
+}
+
+/// Applies `event` and saves the result the way the app does, then
+/// commits it to the git history when that is on.
+async fn record(
+    state: &mut SyncState,
+    settings: &Settings,
+    event: Event,
+    message: String,
+) -> Result<(), String> {
+    let tasks = state.store.lock().unwrap().load_tasks()?;
+    let notes = state.store.lock().unwrap().load_notes()?;
+    state.store.lock().unwrap().append(&event)?;
+    let (tasks, notes) = crate::replay(tasks, notes, [event.clone()]);
+    match event {
+        Event::Notes { .. } => state.store.lock().unwrap().save_notes(&notes)?,
+        _ => state.store.lock().unwrap().save_tasks(&tasks)?,
+    }
+    if let Some(file) = state.instance_lock.take() {
+        lock::release(file);
+    }
+
+    if settings.git_history {
+        let dir = &state.base_path;
+        git::ensure_repo(dir).await?;
+        if git::commit(dir, &message).await? && !settings.git_remote.is_empty() {
+            git::sync_remote(dir, &settings.git_remote).await?;
+        }
+    }
+    Ok(())
+}
+
+fn line(task: &Task) -> String {
+    let check = if task.done { "x" } else { " " };
+    let marker = match task.state {
+        TaskState::Normal => "",
+        TaskState::Chosen => " !",
+        TaskState::Uncertain => " ?",
+    };
+    let id = task.task_id.to_string();
+    format!("{}  [{check}] {}{marker}", &id[..8], task.task_text)
+}
+
+/// The theme the app shows, for HTML pages.
+fn load_theme(state: &SyncState, settings: &Settings) -> Theme {
+    std::fs::read_to_string(theme::theme_path(&state.themes_path, &settings.theme))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .or_else(|| {
+            Theme::bundled()
+                .into_iter()
+                .find(|(name, _)| *name == settings.theme)
+                .map(|(_, theme)| theme)
+        })
+        .unwrap_or_default()
+}
+
+pub async fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Add { text } => {
+            let text = text.join(" ");
+            let request = ipc::Request::Add { text: text.clone() };
+            if let Some(response) = forward(request).await? {
+                println!("{}", response.task_id.unwrap_or_default());
+                return Ok(());
+            }
+
+            let (mut state, settings) = open(true).await?;
+            let task = Task::quick_add(&text);
+            if task.task_text.is_empty() {
+                return Err("the task has no text".to_string());
+            }
+            let message = format!("Add \"{}\"", task.task_text);
+            let task_id = task.task_id;
+            record(&mut state, &settings, Event::Add { task }, message).await?;
+            println!("{task_id}");
+            Ok(())
+        }
+
+        Command::List { filter, json } => {
+            let (state, _) = open(false).await?;
+            let tasks = state.store.lock().unwrap().load_tasks()?;
+            let shown: Vec<Task> = tasks
+                .into_iter()
+                .rev()
+                .filter(|task| filter.shows(task, ""))
+                .collect();
+            if json {
+                println!("{}", table::export(Format::Json, &shown, "")?);
+            } else {
+                for task in &shown {
+                    println!("{}", line(task));
+                }
+            }
+            Ok(())
+        }
+
+        Command::Done { id_prefix } => {
+            let request = ipc::Request::Complete {
+                task_id: id_prefix.clone(),
+            };
+            if forward(request).await?.is_some() {
+                return Ok(());
+            }
+
+            let (mut state, settings) = open(true).await?;
+            let tasks = state.store.lock().unwrap().load_tasks()?;
+            let task = find_task(&tasks, &id_prefix)?;
+            let message = format!("Complete \"{}\"", task.task_text);
+            let event = Event::CheckBox {
+                task_id: task.task_id,
+                done: true,
+                at: Utc::now(),
+            };
+            record(&mut state, &settings, event, message).await
+        }
+
+        Command::Notes {
+            command: NotesCommand::Show,
+        } => {
+            let (state, _) = open(false).await?;
+            let notes = state.store.lock().unwrap().load_notes()?;
+            if !notes.is_empty() {
+                println!("{}", notes.trim_end_matches('\n'));
+            }
+            Ok(())
+        }
+
+        Command::Notes {
+            command: NotesCommand::Append { text },
+        } => {
+            let text = if text.is_empty() {
+                let mut text = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut text)
+                    .map_err(|e| e.to_string())?;
+                text
+            } else {
+                text.join(" ")
+            };
+            let text = text.trim();
+            if text.is_empty() {
+                return Ok(());
+            }
+
+            let request = ipc::Request::AppendNote {
+                text: text.to_string(),
+            };
+            if forward(request).await?.is_some() {
+                return Ok(());
+            }
+
+            let (mut state, settings) = open(true).await?;
+            let notes = append_paragraph(&state.store.lock().unwrap().load_notes()?, text);
+            let event = Event::Notes {
+                notes,
+                at: Utc::now(),
+            };
+            record(&mut state, &settings, event, "Append to notes".to_string()).await
+        }
+
+        Command::Export { format, output } => {
+            let (state, settings) = open(false).await?;
+            let tasks = state.store.lock().unwrap().load_tasks()?;
+            let notes = state.store.lock().unwrap().load_notes()?;
+            let data = match format {
+                Format::Html => {
+                    let shown: Vec<&Task> = tasks.iter().rev().collect();
+                    let theme = load_theme(&state, &settings);
+                    html::export("cardamom-chai: all tasks", &shown, &notes, &theme)
+                }
+                format => table::export(format, &tasks, &notes)?,
+            };
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, data).map_err(|e| format!("{}: {e}", path.display()))
+                }
+                None => {
+                    print!("{data}");
+                    Ok(())
+                }
+            }
+        }
+    }
+}
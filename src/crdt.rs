@@ -0,0 +1,865 @@
+use crate::settings::{SETTINGS_FILENAME, Settings};
+use crate::storage::{self, Storage};
+use crate::{Modified, Task, TaskState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use uuid::Uuid;
+
+/// Hybrid logical timestamp. Ordering is total, so every replica picks the
+/// same winner for concurrent writes.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Stamp {
+    pub time: i64,
+    pub counter: u32,
+    pub replica: Uuid,
+}
+
+impl Stamp {
+    fn datetime(self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.time).unwrap_or_default()
+    }
+}
+
+pub struct Clock {
+    replica: Uuid,
+    last: Stamp,
+}
+
+impl Clock {
+    pub fn new(replica: Uuid) -> Self {
+        Clock {
+            replica,
+            last: Stamp::default(),
+        }
+    }
+
+    /// A stamp greater than every stamp issued or observed so far.
+    pub fn tick(&mut self) -> Stamp {
+        let now = Utc::now().timestamp_millis();
+        self.last = if now > self.last.time {
+            Stamp {
+                time: now,
+                counter: 0,
+                replica: self.replica,
+            }
+        } else {
+            Stamp {
+                time: self.last.time,
+                counter: self.last.counter + 1,
+                replica: self.replica,
+            }
+        };
+        self.last
+    }
+
+    pub fn observe(&mut self, stamp: Stamp) {
+        if stamp > self.last {
+            self.last = stamp;
+        }
+    }
+}
+
+/// Last-writer-wins register.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lww<T> {
+    pub value: T,
+    pub stamp: Stamp,
+}
+
+impl<T: Clone> Lww<T> {
+    fn new(value: T, stamp: Stamp) -> Self {
+        Lww { value, stamp }
+    }
+
+    fn set(&mut self, value: T, stamp: Stamp) {
+        self.value = value;
+        self.stamp = stamp;
+    }
+
+    fn merge(&mut self, other: &Lww<T>) {
+        if other.stamp > self.stamp {
+            *self = other.clone();
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Elem<T> {
+    id: Stamp,
+    origin: Option<Stamp>,
+    value: T,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+}
+
+/// Replicated growable array: a sequence where every element remembers the
+/// element it was inserted after. Deleted elements stay as tombstones so
+/// later inserts can still find their origin, until `Doc::collect_garbage`
+/// finds no replica can insert after them any more.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rga<T> {
+    elems: Vec<Elem<T>>,
+    /// Elements whose origin has not arrived yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pending: Vec<Elem<T>>,
+}
+
+impl<T> Default for Rga<T> {
+    fn default() -> Self {
+        Rga {
+            elems: vec![],
+            pending: vec![],
+        }
+    }
+}
+
+impl<T: Clone> Rga<T> {
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.elems.iter().filter(|e| !e.deleted).map(|e| &e.value)
+    }
+
+    /// Ids of the placed elements. Pending ones may lack earlier changes.
+    fn ids(&self) -> impl Iterator<Item = Stamp> {
+        self.elems.iter().map(|e| e.id)
+    }
+
+    /// Ids of the elements not deleted, including those still pending.
+    fn live_ids(&self) -> impl Iterator<Item = Stamp> {
+        self.elems
+            .iter()
+            .chain(&self.pending)
+            .filter(|e| !e.deleted)
+            .map(|e| e.id)
+    }
+
+    fn visible_ids(&self) -> Vec<Stamp> {
+        self.elems
+            .iter()
+            .filter(|e| !e.deleted)
+            .map(|e| e.id)
+            .collect()
+    }
+
+    /// Places `elem` after its origin, or holds it back until the origin
+    /// arrives.
+    fn integrate(&mut self, elem: Elem<T>) {
+        let mut i = match elem.origin {
+            None => 0,
+            Some(origin) => match self.elems.iter().position(|e| e.id == origin) {
+                Some(i) => i + 1,
+                None => {
+                    self.pending.push(elem);
+                    return;
+                }
+            },
+        };
+        // concurrent inserts after the same origin are ordered newest first
+        while i < self.elems.len() && self.elems[i].id > elem.id {
+            i += 1;
+        }
+        let id = elem.id;
+        self.elems.insert(i, elem);
+
+        let (waiting, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|e| e.origin == Some(id));
+        self.pending = pending;
+        for elem in waiting {
+            self.integrate(elem);
+        }
+    }
+
+    /// Inserts `value` after the element `origin` (or at the start).
+    pub fn insert_after(&mut self, origin: Option<Stamp>, value: T, id: Stamp) {
+        self.integrate(Elem {
+            id,
+            origin,
+            value,
+            deleted: false,
+        });
+    }
+
+    pub fn delete(&mut self, id: Stamp) {
+        if let Some(e) = self
+            .elems
+            .iter_mut()
+            .chain(&mut self.pending)
+            .find(|e| e.id == id)
+        {
+            e.deleted = true;
+        }
+    }
+
+    /// Adds the elements and deletions of `other`. Tombstones `collected`
+    /// says were already dropped here are not brought back.
+    fn merge(&mut self, other: &Rga<T>, collected: impl Fn(Stamp) -> bool) {
+        let index: HashMap<Stamp, (bool, usize)> = self
+            .elems
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.id, (false, i)))
+            .chain(
+                self.pending
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| (e.id, (true, i))),
+            )
+            .collect();
+
+        let mut missing = vec![];
+        for elem in other.elems.iter().chain(&other.pending) {
+            match index.get(&elem.id) {
+                Some(&(false, i)) => self.elems[i].deleted |= elem.deleted,
+                Some(&(true, i)) => self.pending[i].deleted |= elem.deleted,
+                None if elem.deleted && collected(elem.id) => {}
+                None => missing.push(elem.clone()),
+            }
+        }
+        // other's order puts every origin before the elements inserted after it
+        for elem in missing {
+            self.integrate(elem);
+        }
+    }
+
+    /// Drops the tombstones `stable` allows that nothing is inserted after.
+    /// Inserts always follow their origin, so walking backwards sees every
+    /// element's children before the element.
+    fn collect(&mut self, stable: impl Fn(Stamp) -> bool) {
+        let mut origins: HashSet<Stamp> = self.pending.iter().filter_map(|e| e.origin).collect();
+        let mut kept = vec![];
+        for elem in std::mem::take(&mut self.elems).into_iter().rev() {
+            if elem.deleted && !origins.contains(&elem.id) && stable(elem.id) {
+                continue;
+            }
+            origins.extend(elem.origin);
+            kept.push(elem);
+        }
+        kept.reverse();
+        self.elems = kept;
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    text: Lww<String>,
+    done: Lww<bool>,
+    state: Lww<TaskState>,
+    deleted: Lww<bool>,
+}
+
+impl Entry {
+    fn merge(&mut self, other: &Entry) {
+        self.text.merge(&other.text);
+        self.done.merge(&other.done);
+        self.state.merge(&other.state);
+        self.deleted.merge(&other.deleted);
+    }
+
+    fn stamps(&self) -> [Stamp; 4] {
+        [
+            self.text.stamp,
+            self.done.stamp,
+            self.state.stamp,
+            self.deleted.stamp,
+        ]
+    }
+}
+
+/// The latest stamp of each replica that a doc holds every earlier change of.
+type Seen = BTreeMap<Uuid, Stamp>;
+
+fn has_seen(seen: &Seen, stamp: Stamp) -> bool {
+    seen.get(&stamp.replica).is_some_and(|last| *last >= stamp)
+}
+
+/// The replicated state of one profile: an LWW map per task field keyed by
+/// `task_id`, a sequence for task order and a character sequence for notes.
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Doc {
+    tasks: BTreeMap<Uuid, Entry>,
+    order: Rga<Uuid>,
+    notes: Rga<char>,
+    /// Kept so changes stay seen after their tombstones are collected.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    seen: Seen,
+}
+
+impl Doc {
+    /// What `seen` records joined with every stamp the doc holds. A replica
+    /// writes all its changes, and docs only ever merge whole, so holding a
+    /// replica's stamp means holding all its earlier changes too.
+    fn seen(&self) -> Seen {
+        let mut seen = self.seen.clone();
+        let stamps = self
+            .tasks
+            .values()
+            .flat_map(Entry::stamps)
+            .chain(self.order.ids())
+            .chain(self.notes.ids());
+        for stamp in stamps {
+            let last = seen.entry(stamp.replica).or_default();
+            *last = (*last).max(stamp);
+        }
+        seen
+    }
+
+    pub fn merge(&mut self, other: &Doc) {
+        let seen = self.seen();
+        // what this doc has seen but lacks was collected here
+        let collected = |stamp| has_seen(&seen, stamp);
+        for (id, entry) in &other.tasks {
+            match self.tasks.get_mut(id) {
+                Some(mine) => mine.merge(entry),
+                None if entry.deleted.value && collected(entry.deleted.stamp) => {}
+                None => {
+                    self.tasks.insert(*id, entry.clone());
+                }
+            }
+        }
+        self.order.merge(&other.order, collected);
+        self.notes.merge(&other.notes, collected);
+
+        let mut joined = seen;
+        for (replica, stamp) in other.seen() {
+            let last = joined.entry(replica).or_default();
+            *last = (*last).max(stamp);
+        }
+        self.seen = joined;
+    }
+
+    pub fn max_stamp(&self) -> Stamp {
+        self.seen().into_values().max().unwrap_or_default()
+    }
+
+    /// Drops the tombstones of deleted tasks and notes characters that every
+    /// doc in `replicas`, one per replica, has seen and no longer shows.
+    /// None of them can insert after such a tombstone or bring it back.
+    /// Does nothing unless this doc has merged all of `replicas`, as
+    /// otherwise one of them may hold an element inserted after a tombstone.
+    pub fn collect_garbage(&mut self, replicas: &[Doc]) {
+        self.seen = self.seen();
+        let seen: Vec<Seen> = replicas.iter().map(Doc::seen).collect();
+        let merged = seen
+            .iter()
+            .flat_map(|seen| seen.values())
+            .all(|stamp| has_seen(&self.seen, *stamp));
+        if !merged {
+            return;
+        }
+        let seen_by_all = |stamp| seen.iter().all(|seen| has_seen(seen, stamp));
+
+        let live_tasks: HashSet<Uuid> = replicas
+            .iter()
+            .flat_map(|doc| &doc.tasks)
+            .filter(|(_, entry)| !entry.deleted.value)
+            .map(|(id, _)| *id)
+            .collect();
+        self.tasks.retain(|id, entry| {
+            !entry.deleted.value || !seen_by_all(entry.deleted.stamp) || live_tasks.contains(id)
+        });
+
+        let live_order: HashSet<Stamp> = replicas.iter().flat_map(|d| d.order.live_ids()).collect();
+        self.order
+            .collect(|id| seen_by_all(id) && !live_order.contains(&id));
+        let live_notes: HashSet<Stamp> = replicas.iter().flat_map(|d| d.notes.live_ids()).collect();
+        self.notes
+            .collect(|id| seen_by_all(id) && !live_notes.contains(&id));
+    }
+
+    pub fn tasks(&self) -> Vec<Task> {
+        let mut ids: Vec<Uuid> = vec![];
+        for id in self.order.values() {
+            if !ids.contains(id) {
+                ids.push(*id);
+            }
+        }
+
+        ids.iter()
+            .filter_map(|id| Some((id, self.tasks.get(id)?)))
+            .filter(|(_, entry)| !entry.deleted.value)
+            .map(|(id, entry)| Task {
+                task_id: *id,
+                task_text: entry.text.value.clone(),
+                done: entry.done.value,
+                state: entry.state.value,
+                modified: Modified {
+                    text: entry.text.stamp.datetime(),
+                    done: entry.done.stamp.datetime(),
+                    state: entry.state.stamp.datetime(),
+                },
+            })
+            .collect()
+    }
+
+    pub fn notes(&self) -> String {
+        self.notes.values().collect()
+    }
+
+    /// Records the changes needed to turn the current task list into `tasks`.
+    pub fn set_tasks(&mut self, clock: &mut Clock, tasks: &[Task]) {
+        for task in tasks {
+            match self.tasks.get_mut(&task.task_id) {
+                Some(entry) => {
+                    if entry.text.value != task.task_text {
+                        entry.text.set(task.task_text.clone(), clock.tick());
+                    }
+                    if entry.done.value != task.done {
+                        entry.done.set(task.done, clock.tick());
+                    }
+                    if entry.state.value != task.state {
+                        entry.state.set(task.state, clock.tick());
+                    }
+                    if entry.deleted.value {
+                        entry.deleted.set(false, clock.tick());
+                    }
+                }
+                None => {
+                    let stamp = clock.tick();
+                    self.tasks.insert(
+                        task.task_id,
+                        Entry {
+                            text: Lww::new(task.task_text.clone(), stamp),
+                            done: Lww::new(task.done, stamp),
+                            state: Lww::new(task.state, stamp),
+                            deleted: Lww::new(false, stamp),
+                        },
+                    );
+                }
+            }
+        }
+
+        // keep the sequence in step with the list: drop removed ids, append new ones
+        let positions: Vec<(Stamp, Uuid)> = self
+            .order
+            .elems
+            .iter()
+            .filter(|e| !e.deleted)
+            .map(|e| (e.id, e.value))
+            .collect();
+        for (stamp, id) in &positions {
+            if !tasks.iter().any(|t| t.task_id == *id) {
+                self.order.delete(*stamp);
+            }
+        }
+        for (id, entry) in self.tasks.iter_mut() {
+            if !entry.deleted.value && !tasks.iter().any(|t| t.task_id == *id) {
+                entry.deleted.set(true, clock.tick());
+            }
+        }
+
+        let mut last = self.order.visible_ids().last().copied();
+        for task in tasks {
+            if !self.order.values().any(|id| *id == task.task_id) {
+                let stamp = clock.tick();
+                self.order.insert_after(last, task.task_id, stamp);
+                last = Some(stamp);
+            }
+        }
+    }
+
+    /// Records `notes` as the minimal replacement of the differing middle
+    /// section of the current text.
+    pub fn set_notes(&mut self, clock: &mut Clock, notes: &str) {
+        let ids = self.notes.visible_ids();
+        let old: Vec<char> = self.notes.values().copied().collect();
+        let new: Vec<char> = notes.chars().collect();
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        for id in &ids[prefix..old.len() - suffix] {
+            self.notes.delete(*id);
+        }
+        let mut last = prefix.checked_sub(1).map(|i| ids[i]);
+        for c in &new[prefix..new.len() - suffix] {
+            let stamp = clock.tick();
+            self.notes.insert_after(last, *c, stamp);
+            last = Some(stamp);
+        }
+    }
+}
+
+/// This device's replica of a profile's CRDT store.
+///
+/// Every replica writes only its own `<replica id>.json` inside `dir` and
+/// merges all other files on load, so a synced folder never produces
+/// conflicting copies.
+pub struct Replica {
+    dir: PathBuf,
+    id: Uuid,
+    clock: Clock,
+    doc: Doc,
+}
+
+impl Replica {
+    /// A replica that has read nothing yet; `reload` reads the files.
+    pub fn new(dir: &Path, id: Uuid) -> Replica {
+        Replica {
+            dir: dir.to_path_buf(),
+            id,
+            clock: Clock::new(id),
+            doc: Doc::default(),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(format!("{}.json", self.id))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Merges every replica file in the directory into this one, then drops
+    /// the tombstones all of them are done with.
+    pub fn reload(&mut self) {
+        let files = std::fs::read_dir(&self.dir).into_iter().flatten().flatten();
+        let mut docs = vec![];
+        let mut unreadable = false;
+        for entry in files {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let doc = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|data| serde_json::from_str::<Doc>(&data).ok());
+            match doc {
+                Some(doc) => {
+                    self.doc.merge(&doc);
+                    docs.push(doc);
+                }
+                // perhaps half synced; its replica may still need tombstones
+                None => unreadable = true,
+            }
+        }
+        self.clock.observe(self.doc.max_stamp());
+        if !unreadable {
+            self.doc.collect_garbage(&docs);
+        }
+    }
+
+    pub fn tasks(&self) -> Vec<Task> {
+        self.doc.tasks()
+    }
+
+    pub fn notes(&self) -> String {
+        self.doc.notes()
+    }
+
+    pub fn set_tasks(&mut self, tasks: &[Task]) {
+        self.doc.set_tasks(&mut self.clock, tasks);
+    }
+
+    pub fn set_notes(&mut self, notes: &str) {
+        self.doc.set_notes(&mut self.clock, notes);
+    }
+
+    pub fn snapshot(&self) -> String {
+        serde_json::to_string(&self.doc).expect("failed to serialize")
+    }
+
+    fn write(&self) -> Result<(), String> {
+        let path = self.path();
+        std::fs::write(&path, self.snapshot()).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// The replica is shared with the watcher that merges other devices' files.
+/// Settings live next to the replica directory, as they are per device.
+impl Storage for Arc<Mutex<Replica>> {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        let mut replica = self.lock().unwrap();
+        replica.reload();
+        Ok(replica.tasks())
+    }
+
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        let mut replica = self.lock().unwrap();
+        replica.set_tasks(tasks);
+        replica.write()
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        let mut replica = self.lock().unwrap();
+        replica.reload();
+        Ok(replica.notes())
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        let mut replica = self.lock().unwrap();
+        replica.set_notes(notes);
+        replica.write()
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        storage::read_settings(&self.lock().unwrap().dir.with_file_name(SETTINGS_FILENAME))
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        let path = self.lock().unwrap().dir.with_file_name(SETTINGS_FILENAME);
+        storage::write_settings(&path, settings)
+    }
+
+    fn keeps_existing(&self) -> bool {
+        true
+    }
+}
+
+/// The id naming this device's replica files. It is kept in the local data
+/// dir rather than next to the databases, which may be a synced folder,
+/// and read once per run.
+pub fn replica_id() -> Uuid {
+    static ID: OnceLock<Uuid> = OnceLock::new();
+    *ID.get_or_init(read_replica_id)
+}
+
+fn read_replica_id() -> Uuid {
+    let path = dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("cardamom-chai")
+        .join("replica-id");
+
+    if let Some(id) = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|data| data.trim().parse().ok())
+    {
+        return id;
+    }
+
+    let id = Uuid::new_v4();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    std::fs::write(&path, id.to_string()).ok();
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const REPLICAS: usize = 3;
+
+    struct Site {
+        doc: Doc,
+        clock: Clock,
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Add(usize, String),
+        Edit(usize, usize, String),
+        Toggle(usize, usize),
+        Cycle(usize, usize),
+        Remove(usize, usize),
+        Notes(usize, usize, usize, String),
+        Sync(usize, usize),
+        /// Collects garbage, after merging every replica like
+        /// `Replica::reload` when the flag is set.
+        Collect(usize, bool),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        let site = 0..REPLICAS;
+        prop_oneof![
+            (site.clone(), "[a-c]{1,3}").prop_map(|(s, text)| Op::Add(s, text)),
+            (site.clone(), any::<usize>(), "[a-c]{1,3}").prop_map(|(s, k, t)| Op::Edit(s, k, t)),
+            (site.clone(), any::<usize>()).prop_map(|(s, k)| Op::Toggle(s, k)),
+            (site.clone(), any::<usize>()).prop_map(|(s, k)| Op::Cycle(s, k)),
+            (site.clone(), any::<usize>()).prop_map(|(s, k)| Op::Remove(s, k)),
+            (site.clone(), any::<usize>(), 0..4usize, "[x-z]{0,4}")
+                .prop_map(|(s, at, cut, text)| Op::Notes(s, at, cut, text)),
+            (site.clone(), site.clone()).prop_map(|(from, to)| Op::Sync(from, to)),
+            (site, any::<bool>()).prop_map(|(s, merge)| Op::Collect(s, merge)),
+        ]
+    }
+
+    fn sites() -> Vec<Site> {
+        (0..REPLICAS)
+            .map(|_| Site {
+                doc: Doc::default(),
+                clock: Clock::new(Uuid::new_v4()),
+            })
+            .collect()
+    }
+
+    fn edit_task(site: &mut Site, k: usize, change: impl FnOnce(&mut Vec<Task>, usize)) {
+        let mut tasks = site.doc.tasks();
+        if !tasks.is_empty() {
+            let k = k % tasks.len();
+            change(&mut tasks, k);
+            site.doc.set_tasks(&mut site.clock, &tasks);
+        }
+    }
+
+    fn apply(sites: &mut [Site], op: Op) {
+        match op {
+            Op::Add(s, task_text) => {
+                let site = &mut sites[s];
+                let mut tasks = site.doc.tasks();
+                tasks.push(Task {
+                    task_id: Uuid::new_v4(),
+                    task_text,
+                    ..Task::default()
+                });
+                site.doc.set_tasks(&mut site.clock, &tasks);
+            }
+            Op::Edit(s, k, text) => edit_task(&mut sites[s], k, |tasks, k| {
+                tasks[k].task_text = text;
+            }),
+            Op::Toggle(s, k) => edit_task(&mut sites[s], k, |tasks, k| {
+                tasks[k].done = !tasks[k].done;
+            }),
+            Op::Cycle(s, k) => edit_task(&mut sites[s], k, |tasks, k| {
+                tasks[k].state = match tasks[k].state {
+                    TaskState::Normal => TaskState::Chosen,
+                    TaskState::Chosen => TaskState::Uncertain,
+                    TaskState::Uncertain => TaskState::Normal,
+                };
+            }),
+            Op::Remove(s, k) => edit_task(&mut sites[s], k, |tasks, k| {
+                tasks.remove(k);
+            }),
+            Op::Notes(s, at, cut, text) => {
+                let site = &mut sites[s];
+                let mut notes: Vec<char> = site.doc.notes().chars().collect();
+                let at = at % (notes.len() + 1);
+                let cut = cut.min(notes.len() - at);
+                notes.splice(at..at + cut, text.chars());
+                let notes: String = notes.into_iter().collect();
+                site.doc.set_notes(&mut site.clock, &notes);
+            }
+            Op::Sync(from, to) => {
+                let doc = sites[from].doc.clone();
+                let site = &mut sites[to];
+                site.doc.merge(&doc);
+                site.clock.observe(site.doc.max_stamp());
+            }
+            Op::Collect(s, merge) => {
+                let docs: Vec<Doc> = sites.iter().map(|site| site.doc.clone()).collect();
+                if merge {
+                    for doc in &docs {
+                        sites[s].doc.merge(doc);
+                    }
+                }
+                sites[s].doc.collect_garbage(&docs);
+            }
+        }
+    }
+
+    fn same(a: &Doc, b: &Doc) -> bool {
+        a.tasks() == b.tasks() && a.notes() == b.notes()
+    }
+
+    proptest! {
+        #[test]
+        fn replicas_converge(ops in prop::collection::vec(op(), 0..60)) {
+            let mut sites = sites();
+            for op in ops {
+                apply(&mut sites, op);
+            }
+            let docs: Vec<Doc> = sites.iter().map(|site| site.doc.clone()).collect();
+
+            // every merge order of the final states gives the same result
+            let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+            let merged: Vec<Doc> = orders
+                .iter()
+                .map(|order| {
+                    let mut doc = Doc::default();
+                    for &i in order {
+                        doc.merge(&docs[i]);
+                    }
+                    doc
+                })
+                .collect();
+            for doc in &merged {
+                prop_assert!(same(doc, &merged[0]));
+            }
+
+            // so does every replica merging the others into its own state
+            for (i, site) in sites.iter_mut().enumerate() {
+                for other in docs.iter().cycle().skip(i + 1).take(REPLICAS - 1) {
+                    site.doc.merge(other);
+                }
+                prop_assert!(same(&site.doc, &merged[0]));
+            }
+
+            // and collecting garbage afterwards changes nothing visible
+            let docs: Vec<Doc> = sites.iter().map(|site| site.doc.clone()).collect();
+            for site in &mut sites {
+                site.doc.collect_garbage(&docs);
+                prop_assert!(same(&site.doc, &merged[0]));
+            }
+            let mut doc = docs[0].clone();
+            for site in &sites {
+                doc.merge(&site.doc);
+            }
+            prop_assert!(same(&doc, &merged[0]));
+        }
+    }
+
+    #[test]
+    fn elements_wait_for_their_origin() {
+        let mut clock = Clock::new(Uuid::new_v4());
+        let mut doc = Doc::default();
+        doc.set_notes(&mut clock, "ab");
+        let mut late = Rga::default();
+        for elem in doc.notes.elems.iter().rev() {
+            late.integrate(elem.clone());
+        }
+        assert_eq!(late.pending.len(), 0);
+        assert_eq!(late.values().collect::<String>(), "ab");
+
+        let mut first = Rga::default();
+        first.integrate(doc.notes.elems[1].clone());
+        assert_eq!(first.values().count(), 0);
+        first.integrate(doc.notes.elems[0].clone());
+        assert_eq!(first.values().collect::<String>(), "ab");
+    }
+
+    #[test]
+    fn tombstones_are_collected_once_every_replica_has_them() {
+        let mut sites = sites();
+        apply(&mut sites, Op::Notes(0, 0, 0, "hello world".to_string()));
+        apply(&mut sites, Op::Add(0, "task".to_string()));
+        for to in 1..REPLICAS {
+            apply(&mut sites, Op::Sync(0, to));
+        }
+        apply(&mut sites, Op::Notes(1, 5, 6, String::new()));
+        apply(&mut sites, Op::Remove(1, 0));
+
+        // replicas that have not seen the deletions keep the tombstones
+        apply(&mut sites, Op::Collect(1, true));
+        assert_eq!(sites[1].doc.notes.elems.len(), 11);
+        assert_eq!(sites[1].doc.tasks.len(), 1);
+
+        for to in [0, 2] {
+            apply(&mut sites, Op::Sync(1, to));
+        }
+        let stale = sites[2].doc.clone();
+        for site in 0..REPLICAS {
+            apply(&mut sites, Op::Collect(site, false));
+        }
+        for site in &sites {
+            assert_eq!(site.doc.notes(), "hello");
+            assert_eq!(site.doc.notes.elems.len(), 5);
+            assert!(site.doc.tasks.is_empty());
+            assert!(site.doc.order.elems.is_empty());
+        }
+
+        // a copy from before collecting does not bring them back
+        sites[0].doc.merge(&stale);
+        assert_eq!(sites[0].doc.notes.elems.len(), 5);
+        assert!(sites[0].doc.tasks.is_empty());
+    }
+}
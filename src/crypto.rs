@@ -0,0 +1,258 @@
+use crate::settings::{SETTINGS_FILENAME, Settings};
+use crate::storage::{self, Storage};
+use crate::{NOTES_DATABASE_FILENAME, TASK_DATABASE_FILENAME, Task};
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const CIPHER: &str = "xchacha20poly1305";
+const KDF: &str = "argon2id";
+
+/// A key derived from a passphrase, together with the salt it was derived with.
+#[derive(Clone)]
+pub struct Key {
+    salt: [u8; 16],
+    key: [u8; 32],
+}
+
+/// What an encrypted database file contains instead of the plain JSON.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    cipher: String,
+    kdf: String,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl Envelope {
+    fn parse(data: &str) -> Option<Envelope> {
+        serde_json::from_str::<Envelope>(data)
+            .ok()
+            .filter(|envelope| envelope.cipher == CIPHER && envelope.kdf == KDF)
+    }
+
+    fn salt(&self) -> Option<[u8; 16]> {
+        from_hex(&self.salt)?.try_into().ok()
+    }
+}
+
+impl Key {
+    /// Slow on purpose; call it off the UI thread.
+    pub fn derive(passphrase: &str, salt: [u8; 16]) -> Result<Key, String> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| e.to_string())?;
+        Ok(Key { salt, key })
+    }
+
+    /// Derives a key with a fresh random salt.
+    pub fn generate(passphrase: &str) -> Result<Key, String> {
+        let mut salt = [0; 16];
+        OsRng.fill_bytes(&mut salt);
+        Key::derive(passphrase, salt)
+    }
+
+    /// `filename` is authenticated along with the contents, so encrypted
+    /// files cannot be swapped for one another.
+    fn encrypt(&self, filename: &str, plaintext: &str) -> String {
+        let cipher = XChaCha20Poly1305::new((&self.key).into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: filename.as_bytes(),
+        };
+        let data = cipher.encrypt(&nonce, payload).expect("failed to encrypt");
+
+        let envelope = Envelope {
+            cipher: CIPHER.to_string(),
+            kdf: KDF.to_string(),
+            salt: to_hex(&self.salt),
+            nonce: to_hex(&nonce),
+            data: to_hex(&data),
+        };
+        serde_json::to_string_pretty(&envelope).expect("failed to serialize")
+    }
+
+    /// The plaintext of an encrypted database file's contents. Plain
+    /// contents are refused, since anyone could have written them.
+    pub fn open(&self, filename: &str, data: &str) -> Result<String, String> {
+        match Envelope::parse(data) {
+            Some(envelope) => self.decrypt(filename, &envelope),
+            None => Err(format!("{filename} is not encrypted")),
+        }
+    }
+
+    fn decrypt(&self, filename: &str, envelope: &Envelope) -> Result<String, String> {
+        if envelope.salt() != Some(self.salt) {
+            return Err(format!(
+                "{filename} was encrypted with a different passphrase"
+            ));
+        }
+        let nonce = from_hex(&envelope.nonce)
+            .filter(|nonce| nonce.len() == 24)
+            .ok_or_else(|| format!("{filename}: malformed nonce"))?;
+        let data = from_hex(&envelope.data).ok_or_else(|| format!("{filename}: malformed data"))?;
+
+        let cipher = XChaCha20Poly1305::new((&self.key).into());
+        let payload = Payload {
+            msg: &data,
+            aad: filename.as_bytes(),
+        };
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce), payload)
+            .map_err(|_| "wrong passphrase".to_string())?;
+        String::from_utf8(plaintext).map_err(|e| format!("{filename}: {e}"))
+    }
+}
+
+/// Whether any database in `dir` is already encrypted.
+pub fn is_encrypted(dir: &Path) -> bool {
+    [TASK_DATABASE_FILENAME, NOTES_DATABASE_FILENAME]
+        .iter()
+        .filter_map(|filename| std::fs::read_to_string(dir.join(filename)).ok())
+        .any(|data| Envelope::parse(&data).is_some())
+}
+
+/// Derives the key for the databases in `dir` and checks it against them.
+/// When nothing there is encrypted yet, `passphrase` becomes the new one
+/// and the plain databases are encrypted with it.
+pub fn unlock(dir: &Path, passphrase: &str) -> Result<Key, String> {
+    for filename in [TASK_DATABASE_FILENAME, NOTES_DATABASE_FILENAME] {
+        let Some(envelope) = std::fs::read_to_string(dir.join(filename))
+            .ok()
+            .and_then(|data| Envelope::parse(&data))
+        else {
+            continue;
+        };
+        let salt = envelope
+            .salt()
+            .ok_or_else(|| format!("{filename}: malformed salt"))?;
+        let key = Key::derive(passphrase, salt)?;
+        key.decrypt(filename, &envelope)?;
+        return Ok(key);
+    }
+    let key = Key::generate(passphrase)?;
+    Encrypted::new(dir, key.clone()).encrypt_plain()?;
+    Ok(key)
+}
+
+/// `database.json` and `notes-database.json` encrypted with a passphrase.
+/// Plain files are only encrypted by `unlock` when the first passphrase is
+/// chosen; after that they fail to load. Settings stay plain, since they
+/// are needed before unlocking.
+pub struct Encrypted {
+    dir: PathBuf,
+    key: Key,
+}
+
+impl Encrypted {
+    pub fn new(dir: &Path, key: Key) -> Encrypted {
+        Encrypted {
+            dir: dir.to_path_buf(),
+            key,
+        }
+    }
+
+    fn read(&self, filename: &str) -> Result<Option<String>, String> {
+        let path = self.dir.join(filename);
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        self.key.open(filename, &data).map(Some)
+    }
+
+    /// Encrypts the databases that are still plain JSON in place.
+    fn encrypt_plain(&self) -> Result<(), String> {
+        for filename in [TASK_DATABASE_FILENAME, NOTES_DATABASE_FILENAME] {
+            let path = self.dir.join(filename);
+            match std::fs::read_to_string(&path) {
+                Ok(data) if Envelope::parse(&data).is_none() => self.write(filename, &data)?,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("{}: {e}", path.display())),
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, filename: &str, plaintext: &str) -> Result<(), String> {
+        let path = self.dir.join(filename);
+        std::fs::write(&path, self.key.encrypt(filename, plaintext))
+            .map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl Storage for Encrypted {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        match self.read(TASK_DATABASE_FILENAME)? {
+            Some(data) => serde_json::from_str(&data).map_err(|e| e.to_string()),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(tasks).expect("failed to serialize");
+        self.write(TASK_DATABASE_FILENAME, &json)
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        Ok(self.read(NOTES_DATABASE_FILENAME)?.unwrap_or_default())
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        self.write(NOTES_DATABASE_FILENAME, notes)
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        storage::read_settings(&self.dir.join(SETTINGS_FILENAME))
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        storage::write_settings(&self.dir.join(SETTINGS_FILENAME), settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_databases_are_encrypted_by_the_first_passphrase_only() {
+        let dir = std::env::temp_dir().join(format!("cardamom-chai-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let notes = dir.join(NOTES_DATABASE_FILENAME);
+        std::fs::write(&notes, "plain notes").unwrap();
+
+        let key = unlock(&dir, "passphrase").unwrap();
+        assert!(is_encrypted(&dir));
+        let mut store = Encrypted::new(&dir, key.clone());
+        assert_eq!(store.load_notes().unwrap(), "plain notes");
+
+        // a plain file put in place of the encrypted one is refused
+        std::fs::write(&notes, "planted notes").unwrap();
+        assert!(store.load_notes().is_err());
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "planted notes");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/// How long writes must settle before they are committed together.
+const COMMIT_DEBOUNCE: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct Commit {
+    pub hash: String,
+    pub date: String,
+    pub message: String,
+}
+
+const GITIGNORE: &str = "api-token\ncardamom-chai.lock\ncardamom-chai.sock\nsync-merge.log\n";
+
+async fn git_bytes(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("git: {e}"))?;
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let stdout = git_bytes(dir, args).await?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Turns `dir` into a git repository unless it already is one, and keeps
+/// the app's own files out of it.
+pub async fn ensure_repo(dir: &Path) -> Result<(), String> {
+    if git(dir, &["rev-parse", "--git-dir"]).await.is_err() {
+        git(dir, &["init"]).await?;
+    }
+    // repositories made before a file was listed must still never commit it
+    let ignore = dir.join(".gitignore");
+    let mut ignored = tokio::fs::read_to_string(&ignore).await.unwrap_or_default();
+    let missing: Vec<&str> = GITIGNORE
+        .lines()
+        .filter(|line| !ignored.lines().any(|existing| existing.trim() == *line))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if !ignored.is_empty() && !ignored.ends_with('\n') {
+        ignored.push('\n');
+    }
+    for line in missing {
+        ignored.push_str(line);
+        ignored.push('\n');
+    }
+    tokio::fs::write(ignore, ignored)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Commits everything in `dir`. Returns false when there was nothing to commit.
+pub async fn commit(dir: &Path, message: &str) -> Result<bool, String> {
+    git(dir, &["add", "-A"]).await?;
+    if git(dir, &["status", "--porcelain"])
+        .await?
+        .trim()
+        .is_empty()
+    {
+        return Ok(false);
+    }
+
+    // fall back to a local identity so commits work on unconfigured machines
+    let mut args = vec![];
+    if git(dir, &["config", "user.email"]).await.is_err() {
+        args.extend([
+            "-c",
+            "user.name=cardamom-chai",
+            "-c",
+            "user.email=cardamom-chai@localhost",
+        ]);
+    }
+    args.extend(["commit", "-q", "-m", message]);
+    git(dir, &args).await?;
+    Ok(true)
+}
+
+pub fn is_local_remote(remote: &str) -> bool {
+    remote.starts_with("file://") || Path::new(remote).is_absolute()
+}
+
+/// Pulls from and pushes to `remote`, which must be a local path or a
+/// `file://` URL of a bare repository. A pull that cannot be rebased
+/// cleanly is abandoned so local history is never left mid-rebase.
+pub async fn sync_remote(dir: &Path, remote: &str) -> Result<(), String> {
+    if !is_local_remote(remote) {
+        return Err(format!(
+            "only local and file:// remotes are supported: {remote}"
+        ));
+    }
+    let branch = git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+    let branch = branch.trim();
+
+    // the remote branch does not exist before the first push
+    if git(
+        dir,
+        &["ls-remote", "--exit-code", "--heads", remote, branch],
+    )
+    .await
+    .is_ok()
+        && let Err(e) = git(dir, &["pull", "-q", "--rebase", remote, branch]).await
+    {
+        git(dir, &["rebase", "--abort"]).await.ok();
+        return Err(e);
+    }
+    git(
+        dir,
+        &["push", "-q", remote, &format!("HEAD:refs/heads/{branch}")],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Batches change descriptions and commits them once writes have been
+/// quiet for `COMMIT_DEBOUNCE`, then syncs with the remote if one is set.
+pub struct Recorder {
+    dir: PathBuf,
+    remote: String,
+    messages: Arc<Mutex<Vec<String>>>,
+    deadline: Arc<Mutex<Instant>>,
+    committer: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf, remote: String) -> Self {
+        let init_dir = dir.clone();
+        tokio::spawn(async move {
+            if let Err(e) = ensure_repo(&init_dir).await {
+                eprintln!("cardamom-chai: git history: {e}");
+            }
+        });
+
+        Recorder {
+            dir,
+            remote,
+            messages: Arc::default(),
+            deadline: Arc::new(Mutex::new(Instant::now())),
+            committer: None,
+        }
+    }
+
+    pub fn record(&mut self, message: String) {
+        self.messages.lock().unwrap().push(message);
+        *self.deadline.lock().unwrap() = Instant::now() + COMMIT_DEBOUNCE;
+
+        if self.committer.as_ref().is_some_and(|c| !c.is_finished()) {
+            return;
+        }
+        let dir = self.dir.clone();
+        let remote = self.remote.clone();
+        let messages = self.messages.clone();
+        let deadline = self.deadline.clone();
+        self.committer = Some(tokio::spawn(async move {
+            loop {
+                let wait = deadline
+                    .lock()
+                    .unwrap()
+                    .saturating_duration_since(Instant::now());
+                if !wait.is_zero() {
+                    tokio::time::sleep(wait).await;
+                    continue;
+                }
+
+                let messages = std::mem::take(&mut *messages.lock().unwrap());
+                let Some(message) = commit_message(&messages) else {
+                    break;
+                };
+                match commit(&dir, &message).await {
+                    Ok(true) if !remote.is_empty() => {
+                        if let Err(e) = sync_remote(&dir, &remote).await {
+                            eprintln!("cardamom-chai: git sync with {remote}: {e}");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => eprintln!("cardamom-chai: git commit: {e}"),
+                }
+            }
+        }));
+    }
+}
+
+fn commit_message(messages: &[String]) -> Option<String> {
+    let mut unique: Vec<&String> = vec![];
+    for message in messages {
+        if !unique.contains(&message) {
+            unique.push(message);
+        }
+    }
+    match unique.as_slice() {
+        [] => None,
+        [only] => Some(only.to_string()),
+        [first, rest @ ..] => Some(format!(
+            "{first} (+{} more)\n\n{}",
+            rest.len(),
+            unique
+                .iter()
+                .map(|m| format!("- {m}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        )),
+    }
+}
+
+pub async fn log(dir: &Path, limit: usize) -> Vec<Commit> {
+    let limit = format!("-{limit}");
+    let output = git(
+        dir,
+        &["log", &limit, "--date=iso", "--format=%H%x1f%ad%x1f%s"],
+    )
+    .await
+    .unwrap_or_default();
+
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\x1f');
+            Some(Commit {
+                hash: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                message: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The contents of `path` (relative to the repository root) at `hash`.
+pub async fn show(dir: &Path, hash: &str, path: &str) -> Option<String> {
+    git(dir, &["show", &format!("{hash}:{path}")]).await.ok()
+}
+
+/// Like `show`, for binary files.
+pub async fn show_bytes(dir: &Path, hash: &str, path: &str) -> Option<Vec<u8>> {
+    git_bytes(dir, &["show", &format!("{hash}:{path}")])
+        .await
+        .ok()
+}
+
+/// Files directly inside the directory `path` at `hash`.
+pub async fn list_files(dir: &Path, hash: &str, path: &str) -> Vec<String> {
+    git(dir, &["ls-tree", "--name-only", hash, &format!("{path}/")])
+        .await
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,157 @@
+use crate::theme::Theme;
+use crate::{Task, TaskState};
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn style(theme: &Theme) -> String {
+    let (background, text) = if theme.dark {
+        ("#1b1b1b", "#dcdcdc")
+    } else {
+        ("#f8f8f8", "#3c3c3c")
+    };
+    let visuals = &theme.visuals;
+    let background = visuals
+        .panel_fill
+        .map_or(background.to_string(), |c| c.hex());
+    let text = visuals.text.map_or(text.to_string(), |c| c.hex());
+    let link = visuals.hyperlink.map_or("inherit".to_string(), |c| c.hex());
+    let colors = &theme.tasks;
+    let normal = colors.normal.map_or("inherit".to_string(), |c| c.hex());
+    format!(
+        "body {{ font-family: sans-serif; max-width: 48em; margin: 2em auto; padding: 0 1em; \
+         background: {background}; color: {text}; }}\n\
+         a {{ color: {link}; }}\n\
+         ul.tasks {{ list-style: none; padding: 0; }}\n\
+         ul.tasks li {{ margin: 0.3em 0; }}\n\
+         .normal {{ color: {normal}; }}\n\
+         .chosen {{ color: {}; text-decoration: underline; }}\n\
+         .uncertain {{ color: {}; }}\n\
+         .done {{ text-decoration: line-through; opacity: 0.6; }}\n\
+         .exported {{ opacity: 0.6; font-size: small; }}\n",
+        colors.chosen.hex(),
+        colors.uncertain.hex(),
+    )
+}
+
+/// A task styled the way the list shows it: done tasks struck through,
+/// chosen ones underlined and uncertain ones with a `?`.
+fn task_item(task: &Task) -> String {
+    let text = escape(task.task_text.trim_end_matches('*'));
+    let (class, text) = match (task.done, task.state) {
+        (true, _) => ("done", text),
+        (false, TaskState::Normal) => ("normal", text),
+        (false, TaskState::Chosen) => ("chosen", text),
+        (false, TaskState::Uncertain) => ("uncertain", format!("{text}?")),
+    };
+    let checked = if task.done { " checked" } else { "" };
+    format!("<li class=\"{class}\"><input type=\"checkbox\" disabled{checked}> {text}</li>\n")
+}
+
+/// Whether a link to `url` is safe to follow from a shared page: a web or
+/// mail address, or one relative to the page.
+fn is_safe_url(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => ["http", "https", "mailto"]
+            .iter()
+            .any(|safe| scheme.eq_ignore_ascii_case(safe)),
+        _ => true,
+    }
+}
+
+/// `url`, or nothing when it is not safe to follow.
+fn safe_url(url: CowStr) -> CowStr {
+    if is_safe_url(&url) {
+        url
+    } else {
+        CowStr::Borrowed("")
+    }
+}
+
+/// The notes as HTML. Raw HTML in them is shown as text, as in the app.
+fn render_notes(notes: &str) -> String {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(notes, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_url(dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+/// A page with `tasks` in the order given and the notes below them,
+/// colored by `theme`. Styles are inline, so the page needs no other
+/// files to be shared.
+pub fn export(title: &str, tasks: &[&Task], notes: &str, theme: &Theme) -> String {
+    let title = escape(title);
+    let exported = chrono::Local::now().format("%Y-%m-%d %H:%M");
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>\n{}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<p class=\"exported\">Exported {exported}, {} tasks.</p>\n",
+        style(theme),
+        tasks.len(),
+    );
+
+    html.push_str("<ul class=\"tasks\">\n");
+    for task in tasks {
+        html.push_str(&task_item(task));
+    }
+    html.push_str("</ul>\n");
+
+    if !notes.trim().is_empty() {
+        html.push_str("<h2>Notes</h2>\n<div class=\"notes\">\n");
+        html.push_str(&render_notes(notes));
+        html.push_str("</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_link_only_to_web_mail_and_relative_addresses() {
+        let html = render_notes(
+            "[a](https://example.com) [b](mailto:me@example.com) [c](plan.html#week)\n\
+             [d](javascript:alert(1)) [e](JavaScript:alert(1)) ![f](data:image/png;base64,AA)",
+        );
+        assert!(html.contains("href=\"https://example.com\""));
+        assert!(html.contains("href=\"mailto:me@example.com\""));
+        assert!(html.contains("href=\"plan.html#week\""));
+        assert!(!html.to_lowercase().contains("javascript"));
+        assert!(!html.contains("data:"));
+    }
+}
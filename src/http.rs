@@ -0,0 +1,384 @@
+use crate::{Filter, TaskState, ipc};
+use axum::extract::{Path as UrlPath, Query, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+pub const TOKEN_FILENAME: &str = "api-token";
+
+/// Describes the routes `serve` answers.
+const OPENAPI: &str = include_str!("openapi.json");
+
+type Handle = dyn Fn(ipc::Request, oneshot::Sender<ipc::Response>) + Send + Sync;
+
+struct Api {
+    token: String,
+    handle: Box<Handle>,
+}
+
+/// The token requests must bear, created in `dir` the first time and
+/// readable only by its owner.
+pub fn token(dir: &Path) -> Result<String, String> {
+    let path = dir.join(TOKEN_FILENAME);
+    if let Ok(token) = std::fs::read_to_string(&path)
+        && !token.trim().is_empty()
+    {
+        return Ok(token.trim().to_string());
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(token)
+}
+
+/// Serves the REST API on localhost. Every request is passed to `handle`
+/// like those on the socket, so changes go through `update`.
+pub async fn serve<F>(port: u16, token: String, handle: F) -> Result<(), String>
+where
+    F: Fn(ipc::Request, oneshot::Sender<ipc::Response>) + Send + Sync + 'static,
+{
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(|e| format!("http api on port {port}: {e}"))?;
+    axum::serve(listener, router(token, handle))
+        .await
+        .map_err(|e| format!("http api: {e}"))
+}
+
+/// The routes `serve` answers, all but the schema for `token` bearers only.
+fn router<F>(token: String, handle: F) -> Router
+where
+    F: Fn(ipc::Request, oneshot::Sender<ipc::Response>) + Send + Sync + 'static,
+{
+    let api = Arc::new(Api {
+        token,
+        handle: Box::new(handle),
+    });
+    Router::new()
+        .route("/tasks", get(list_tasks).post(add_task))
+        .route(
+            "/tasks/{id}",
+            get(get_task).patch(update_task).delete(delete_task),
+        )
+        .route("/notes", get(get_notes).put(set_notes))
+        .route_layer(middleware::from_fn_with_state(api.clone(), authorize))
+        .route("/openapi.json", get(openapi))
+        .with_state(api)
+}
+
+fn error(status: StatusCode, error: &str) -> Response {
+    (status, Json(json!({ "error": error }))).into_response()
+}
+
+async fn authorize(State(api): State<Arc<Api>>, request: Request, next: Next) -> Response {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // compared in constant time, so the answer's timing gives nothing away
+    let authorized =
+        bearer.is_some_and(|bearer| bearer.as_bytes().ct_eq(api.token.as_bytes()).into());
+    if !authorized {
+        return error(StatusCode::UNAUTHORIZED, "missing or wrong bearer token");
+    }
+    next.run(request).await
+}
+
+/// The app's answer to `request`, or the error response for it.
+async fn ask(api: &Api, request: ipc::Request) -> Result<ipc::Response, Response> {
+    let (reply, response) = oneshot::channel();
+    (api.handle)(request, reply);
+    let response = response
+        .await
+        .unwrap_or_else(|_| ipc::Response::error("the app is closing"));
+    match &response.error {
+        _ if response.ok => Ok(response),
+        Some(e) if response.not_found => Err(error(StatusCode::NOT_FOUND, e)),
+        e => Err(error(
+            StatusCode::BAD_REQUEST,
+            e.as_deref().unwrap_or("failed"),
+        )),
+    }
+}
+
+/// The task in a response about one task.
+fn task(response: ipc::Response) -> Response {
+    match response.tasks.and_then(|tasks| tasks.into_iter().next()) {
+        Some(task) => Json(task).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+async fn openapi() -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], OPENAPI).into_response()
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    #[serde(default)]
+    filter: Filter,
+    /// The text the `search` filter looks for.
+    #[serde(default)]
+    q: String,
+}
+
+async fn list_tasks(State(api): State<Arc<Api>>, Query(query): Query<ListQuery>) -> Response {
+    let request = ipc::Request::Query {
+        filter: query.filter,
+        search: query.q,
+    };
+    match ask(&api, request).await {
+        Ok(response) => Json(response.tasks.unwrap_or_default()).into_response(),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct NewTask {
+    text: String,
+}
+
+async fn add_task(State(api): State<Arc<Api>>, Json(new): Json<NewTask>) -> Response {
+    match ask(&api, ipc::Request::Add { text: new.text }).await {
+        Ok(response) => (StatusCode::CREATED, task(response)).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn get_task(State(api): State<Arc<Api>>, UrlPath(id): UrlPath<Uuid>) -> Response {
+    let request = ipc::Request::Query {
+        filter: Filter::All,
+        search: String::new(),
+    };
+    let found = ask(&api, request).await.map(|response| {
+        response
+            .tasks
+            .unwrap_or_default()
+            .into_iter()
+            .find(|task| task.task_id == id)
+    });
+    match found {
+        Ok(Some(task)) => Json(task).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, &format!("no task has id {id}")),
+        Err(response) => response,
+    }
+}
+
+/// The fields a `PATCH` changes; those left out stay as they are.
+#[derive(Deserialize)]
+struct TaskPatch {
+    task_text: Option<String>,
+    done: Option<bool>,
+    state: Option<TaskState>,
+}
+
+async fn update_task(
+    State(api): State<Arc<Api>>,
+    UrlPath(id): UrlPath<Uuid>,
+    Json(patch): Json<TaskPatch>,
+) -> Response {
+    let request = ipc::Request::Update {
+        task_id: id.to_string(),
+        task_text: patch.task_text,
+        done: patch.done,
+        state: patch.state,
+    };
+    match ask(&api, request).await {
+        Ok(response) => task(response),
+        Err(response) => response,
+    }
+}
+
+async fn delete_task(State(api): State<Arc<Api>>, UrlPath(id): UrlPath<Uuid>) -> Response {
+    let request = ipc::Request::Delete {
+        task_id: id.to_string(),
+    };
+    match ask(&api, request).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct Notes {
+    notes: String,
+}
+
+async fn get_notes(State(api): State<Arc<Api>>) -> Response {
+    match ask(&api, ipc::Request::GetNotes).await {
+        Ok(response) => Json(json!({ "notes": response.notes })).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn set_notes(State(api): State<Arc<Api>>, Json(notes): Json<Notes>) -> Response {
+    let request = ipc::Request::SetNotes { notes: notes.notes };
+    match ask(&api, request).await {
+        Ok(response) => Json(json!({ "notes": response.notes })).into_response(),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cmd, Model, Msg};
+    use serde_json::Value;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const TOKEN: &str = "secret";
+
+    /// Serves the API on a free port, answering requests with `update`
+    /// once `msgs` have been handled.
+    async fn start(msgs: Vec<Msg>) -> SocketAddr {
+        let m = msgs
+            .into_iter()
+            .fold(Model::default(), |m, msg| crate::update(m, msg).0);
+        let model = Mutex::new(Some(m));
+        let handle = move |request, reply: oneshot::Sender<ipc::Response>| {
+            let mut model = model.lock().unwrap();
+            let msg = Msg::IpcRequest(Uuid::new_v4(), request);
+            let (m, cmds) = crate::update(model.take().unwrap(), msg);
+            *model = Some(m);
+            let response = cmds.into_iter().find_map(|cmd| match cmd {
+                Cmd::IpcReply(_, response) => Some(response),
+                _ => None,
+            });
+            reply.send(response.unwrap()).ok();
+        };
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes = router(TOKEN.to_string(), handle);
+        tokio::spawn(async move { axum::serve(listener, routes).await });
+        addr
+    }
+
+    /// `start` with the tasks and notes loaded, and nothing in them.
+    async fn start_loaded() -> SocketAddr {
+        let loaded = vec![Msg::LoadedTasks(vec![]), Msg::LoadedNotes(String::new())];
+        start(loaded).await
+    }
+
+    /// The status and JSON body of the answer to a request.
+    async fn send(
+        addr: SocketAddr,
+        token: Option<&str>,
+        method: &str,
+        path: &str,
+        body: Value,
+    ) -> (u16, Value) {
+        let body = if body.is_null() {
+            String::new()
+        } else {
+            body.to_string()
+        };
+        let auth = token
+            .map(|token| format!("Authorization: Bearer {token}\r\n"))
+            .unwrap_or_default();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{auth}\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn requests_without_the_token_are_refused() {
+        let addr = start_loaded().await;
+        for token in [None, Some("wrong"), Some("secre")] {
+            let (status, body) = send(addr, token, "GET", "/tasks", Value::Null).await;
+            assert_eq!(status, 401);
+            assert!(body["error"].is_string());
+        }
+        let add = json!({ "text": "sneak in" });
+        assert_eq!(send(addr, None, "POST", "/tasks", add).await.0, 401);
+        let (status, _) = send(addr, None, "GET", "/openapi.json", Value::Null).await;
+        assert_eq!(status, 200);
+    }
+
+    #[tokio::test]
+    async fn tasks_are_listed_added_and_completed() {
+        let addr = start_loaded().await;
+        let token = Some(TOKEN);
+        let (status, tasks) = send(addr, token, "GET", "/tasks", Value::Null).await;
+        assert_eq!((status, tasks), (200, json!([])));
+
+        let add = json!({ "text": "water the plants!" });
+        let (status, task) = send(addr, token, "POST", "/tasks", add).await;
+        assert_eq!(status, 201);
+        assert_eq!(task["task_text"], "water the plants");
+        assert_eq!(task["state"], "Chosen");
+        assert_eq!(task["done"], false);
+
+        let path = format!("/tasks/{}", task["task_id"].as_str().unwrap());
+        let complete = json!({ "done": true });
+        let (status, task) = send(addr, token, "PATCH", &path, complete).await;
+        assert_eq!((status, &task["done"]), (200, &json!(true)));
+
+        let (status, tasks) = send(addr, token, "GET", "/tasks?filter=done", Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(tasks.as_array().unwrap().len(), 1);
+        assert_eq!(tasks[0]["task_id"], task["task_id"]);
+        let (_, pending) = send(addr, token, "GET", "/tasks?filter=pending", Value::Null).await;
+        assert_eq!(pending, json!([]));
+    }
+
+    #[tokio::test]
+    async fn searches_look_for_the_query_text() {
+        let addr = start_loaded().await;
+        let token = Some(TOKEN);
+        for text in ["water the plants", "pay rent"] {
+            send(addr, token, "POST", "/tasks", json!({ "text": text })).await;
+        }
+        let search = "/tasks?filter=search&q=wtr";
+        let (status, tasks) = send(addr, token, "GET", search, Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(tasks.as_array().unwrap().len(), 1);
+        assert_eq!(tasks[0]["task_text"], "water the plants");
+    }
+
+    #[tokio::test]
+    async fn changes_wait_for_the_tasks_to_load() {
+        let addr = start(vec![]).await;
+        let token = Some(TOKEN);
+        let add = json!({ "text": "too early" });
+        let (status, body) = send(addr, token, "POST", "/tasks", add).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "the app is still loading");
+        let path = format!("/tasks/{}", Uuid::new_v4());
+        let (status, _) = send(addr, token, "PATCH", &path, json!({ "done": true })).await;
+        assert_eq!(status, 400);
+    }
+}
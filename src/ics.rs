@@ -0,0 +1,245 @@
+use crate::{Task, TaskState};
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+/// Task text tokens carrying what VTODOs have fields for, in the
+/// `key:value` style todo.txt uses.
+const DUE_KEY: &str = "due:";
+const RRULE_KEY: &str = "rrule:";
+
+/// Lines longer than this many bytes are folded.
+const LINE_LIMIT: usize = 75;
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => {}
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Appends `line` folded to `LINE_LIMIT` bytes and terminated by CRLF.
+fn push_line(ics: &mut String, line: &str) {
+    let mut rest = line;
+    let mut limit = LINE_LIMIT;
+    while rest.len() > limit {
+        let mut at = limit;
+        while !rest.is_char_boundary(at) {
+            at -= 1;
+        }
+        ics.push_str(&rest[..at]);
+        ics.push_str("\r\n ");
+        rest = &rest[at..];
+        // the leading space of continuation lines counts
+        limit = LINE_LIMIT - 1;
+    }
+    ics.push_str(rest);
+    ics.push_str("\r\n");
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// A VCALENDAR with a VTODO per task. `due:YYYY-MM-DD` and `rrule:...`
+/// tokens in a task's text become its `DUE` and `RRULE`.
+pub fn export(tasks: &[Task]) -> String {
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//cardamom-chai//EN");
+    for task in tasks {
+        let mut summary = vec![];
+        let mut due = None;
+        let mut rrule = None;
+        // split on spaces only, so line breaks in the text are kept
+        for word in task.task_text.trim().split(' ') {
+            if let Some(date) = word
+                .strip_prefix(DUE_KEY)
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            {
+                due = Some(date);
+            } else if let Some(rule) = word.strip_prefix(RRULE_KEY) {
+                rrule = Some(rule);
+            } else {
+                summary.push(word);
+            }
+        }
+
+        push_line(&mut ics, "BEGIN:VTODO");
+        push_line(&mut ics, &format!("UID:{}", task.task_id));
+        push_line(
+            &mut ics,
+            &format!("DTSTAMP:{}", format_time(task.modified.text)),
+        );
+        push_line(&mut ics, &format!("SUMMARY:{}", escape(&summary.join(" "))));
+        if task.done {
+            push_line(&mut ics, "STATUS:COMPLETED");
+            push_line(
+                &mut ics,
+                &format!("COMPLETED:{}", format_time(task.modified.done)),
+            );
+        } else {
+            push_line(&mut ics, "STATUS:NEEDS-ACTION");
+        }
+        match task.state {
+            TaskState::Chosen => push_line(&mut ics, "PRIORITY:1"),
+            TaskState::Uncertain => push_line(&mut ics, "PRIORITY:9"),
+            TaskState::Normal => {}
+        }
+        if let Some(due) = due {
+            push_line(&mut ics, &format!("DUE;VALUE=DATE:{}", due.format("%Y%m%d")));
+        }
+        if let Some(rrule) = rrule {
+            push_line(&mut ics, &format!("RRULE:{rrule}"));
+        }
+        push_line(&mut ics, "END:VTODO");
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+/// The VTODOs in an iCalendar file. High priorities (1-4) make a task
+/// chosen and low ones (6-9) uncertain; `DUE` and `RRULE` are kept as
+/// tokens in the task text. Other components are skipped.
+pub fn import(ics: &str) -> Result<Vec<Task>, String> {
+    // unfold continuation lines first
+    let mut lines: Vec<String> = vec![];
+    for line in ics.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    if !lines.iter().any(|line| line.trim() == "BEGIN:VCALENDAR") {
+        return Err("not an iCalendar file".to_string());
+    }
+
+    let mut tasks = vec![];
+    let mut todo: Option<(Task, Vec<String>)> = None;
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = name.split(';').next().unwrap_or("").to_uppercase();
+        match (name.as_str(), &mut todo) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => {
+                let mut task = Task::new(String::new(), TaskState::Normal);
+                task.task_id = Uuid::nil();
+                todo = Some((task, vec![]));
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VTODO") => {
+                let (mut task, extra) = todo.take().unwrap();
+                task.task_text = [task.task_text.trim().to_string()]
+                    .into_iter()
+                    .chain(extra)
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if task.task_id.is_nil() {
+                    task.task_id = Uuid::new_v4();
+                }
+                tasks.push(task);
+            }
+            ("UID", Some((task, _))) => {
+                task.task_id = Uuid::parse_str(value.trim()).unwrap_or_default();
+            }
+            ("SUMMARY", Some((task, _))) => task.task_text = unescape(value),
+            ("STATUS", Some((task, _))) => {
+                task.done = value.trim().eq_ignore_ascii_case("COMPLETED");
+            }
+            ("PRIORITY", Some((task, _))) => {
+                task.state = match value.trim().parse::<u8>() {
+                    Ok(1..=4) => TaskState::Chosen,
+                    Ok(6..=9) => TaskState::Uncertain,
+                    _ => TaskState::Normal,
+                };
+            }
+            ("DUE", Some((_, extra))) => {
+                if let Some(date) = value
+                    .get(..8)
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+                {
+                    extra.push(format!("{DUE_KEY}{}", date.format("%Y-%m-%d")));
+                }
+            }
+            ("RRULE", Some((_, extra))) => extra.push(format!("{RRULE_KEY}{}", value.trim())),
+            _ => {}
+        }
+    }
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exported_tasks_import_unchanged() {
+        let mut tasks: Vec<Task> = [
+            (
+                "pay rent; call the bank, then\nfile the receipt",
+                TaskState::Normal,
+            ),
+            (
+                "water plants due:2026-11-01 rrule:FREQ=WEEKLY;BYDAY=SA",
+                TaskState::Chosen,
+            ),
+            // "SUMMARY:" is 8 bytes, so byte 75 falls inside an "é"
+            (&"é".repeat(60), TaskState::Uncertain),
+            ("back\\slash", TaskState::Normal),
+        ]
+        .into_iter()
+        .map(|(text, state)| Task::new(text.trim().to_string(), state))
+        .collect();
+        tasks[1].done = true;
+
+        let ics = export(&tasks);
+        assert!(ics.split("\r\n").all(|line| line.len() <= LINE_LIMIT));
+        assert!(ics.contains(&format!("SUMMARY:{}\r\n é", "é".repeat(33))));
+        assert!(ics.contains("SUMMARY:pay rent\\; call the bank\\, then\\nfile the receipt\r\n"));
+        assert!(ics.contains("DUE;VALUE=DATE:20261101\r\n"));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;BYDAY=SA\r\n"));
+        assert!(ics.contains("PRIORITY:1\r\n") && ics.contains("PRIORITY:9\r\n"));
+
+        let imported = import(&ics).unwrap();
+        assert_eq!(imported.len(), tasks.len());
+        for (imported, task) in imported.iter().zip(&tasks) {
+            assert_eq!(imported.task_id, task.task_id);
+            assert_eq!(imported.task_text, task.task_text);
+            assert_eq!(imported.done, task.done);
+            assert!(imported.state == task.state);
+        }
+    }
+
+    #[test]
+    fn priorities_map_to_states() {
+        let states: Vec<TaskState> = ["1", "4", "5", "6", "9", "0"]
+            .iter()
+            .map(|priority| {
+                let ics = format!(
+                    "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nSUMMARY:a\r\nPRIORITY:{priority}\r\n\
+                     END:VTODO\r\nEND:VCALENDAR\r\n"
+                );
+                import(&ics).unwrap()[0].state
+            })
+            .collect();
+        use TaskState::*;
+        assert!(states == [Chosen, Chosen, Normal, Uncertain, Uncertain, Normal]);
+    }
+}
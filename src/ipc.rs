@@ -0,0 +1,162 @@
+use crate::{Filter, Task, TaskState};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+pub const SOCKET_FILENAME: &str = "cardamom-chai.sock";
+
+/// A command for the running app, sent as a line of JSON such as
+/// `{"command": "add", "text": "call back!"}`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Adds a task read like the add box.
+    Add {
+        text: String,
+    },
+    /// Marks the task whose id starts with `task_id` as done.
+    Complete {
+        task_id: String,
+    },
+    /// Changes the given fields of the task whose id starts with `task_id`.
+    Update {
+        task_id: String,
+        #[serde(default)]
+        task_text: Option<String>,
+        #[serde(default)]
+        done: Option<bool>,
+        #[serde(default)]
+        state: Option<TaskState>,
+    },
+    /// Deletes the task whose id starts with `task_id`.
+    Delete {
+        task_id: String,
+    },
+    /// The tasks shown for `filter`, newest first.
+    Query {
+        #[serde(default)]
+        filter: Filter,
+        /// The text the `search` filter looks for.
+        #[serde(default)]
+        search: String,
+    },
+    /// Adds a paragraph to the end of the notes.
+    AppendNote {
+        text: String,
+    },
+    GetNotes,
+    /// Replaces the notes.
+    SetNotes {
+        notes: String,
+    },
+}
+
+/// The answer to a request, sent back as a line of JSON.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The task added, changed or deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<Uuid>,
+    /// The tasks queried, or the task as added or changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tasks: Option<Vec<Task>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Whether the error is that no task has the id.
+    #[serde(skip)]
+    pub not_found: bool,
+}
+
+impl Response {
+    pub fn error(error: impl Into<String>) -> Response {
+        Response {
+            error: Some(error.into()),
+            ..Response::default()
+        }
+    }
+
+    pub fn not_found(error: impl Into<String>) -> Response {
+        Response {
+            not_found: true,
+            ..Response::error(error)
+        }
+    }
+}
+
+pub fn socket_path(dir: &Path) -> PathBuf {
+    dir.join(SOCKET_FILENAME)
+}
+
+/// Answers connections to the socket in `dir`, one request per line.
+/// `handle` is given each request with where to send its response. The
+/// caller must hold the single-instance lock, as a socket left behind
+/// by another instance is replaced.
+pub async fn listen<F>(dir: &Path, handle: F) -> Result<(), String>
+where
+    F: Fn(Request, oneshot::Sender<Response>) + Send + Sync + 'static,
+{
+    let path = socket_path(dir);
+    std::fs::remove_file(&path).ok();
+    let listener = UnixListener::bind(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let handle = Arc::new(handle);
+    loop {
+        let (stream, _) = listener.accept().await.map_err(|e| e.to_string())?;
+        let handle = handle.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let response = match serde_json::from_str(&line) {
+                    Ok(request) => {
+                        let (reply, response) = oneshot::channel();
+                        handle(request, reply);
+                        response
+                            .await
+                            .unwrap_or_else(|_| Response::error("the app is closing"))
+                    }
+                    Err(e) => Response::error(format!("invalid request: {e}")),
+                };
+                let mut json = serde_json::to_string(&response).expect("failed to serialize");
+                json.push('\n');
+                if writer.write_all(json.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// Sends `request` to the app running on `dir`. `None` when no app is
+/// listening there.
+pub async fn send(dir: &Path, request: &Request) -> Result<Option<Response>, String> {
+    let Ok(stream) = UnixStream::connect(socket_path(dir)).await else {
+        return Ok(None);
+    };
+    let (reader, mut writer) = stream.into_split();
+    let mut json = serde_json::to_string(request).expect("failed to serialize");
+    json.push('\n');
+    writer
+        .write_all(json.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("the app closed the connection")?;
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| format!("invalid response: {e}"))
+}
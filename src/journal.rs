@@ -0,0 +1,423 @@
+use crate::settings::{SETTINGS_FILENAME, Settings};
+use crate::storage::{self, Storage};
+use crate::{Task, TaskState};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const JOURNAL_FILENAME: &str = "journal.jsonl";
+pub const SNAPSHOT_FILENAME: &str = "journal-snapshot.json";
+
+/// Events between compacted snapshots.
+const SNAPSHOT_EVERY: usize = 100;
+
+/// A state change as recorded in the journal. Events carry every value
+/// `update` decided (ids, states, times) so replaying them is deterministic.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Add {
+        task: Task,
+    },
+    Reschedule {
+        task: Task,
+    },
+    CheckBox {
+        task_id: Uuid,
+        done: bool,
+        at: DateTime<Utc>,
+    },
+    CycleTaskState {
+        task_id: Uuid,
+        state: TaskState,
+        at: DateTime<Utc>,
+    },
+    Delete {
+        task_id: Uuid,
+        at: DateTime<Utc>,
+    },
+    EditDone {
+        task_id: Uuid,
+        text: String,
+        at: DateTime<Utc>,
+    },
+    Notes {
+        notes: String,
+        at: DateTime<Utc>,
+    },
+    /// Puts the tasks in the order of `task_ids`, e.g. after a merge.
+    Reorder {
+        task_ids: Vec<Uuid>,
+        at: DateTime<Utc>,
+    },
+    /// Changes the finer events cannot express, e.g. duplicate task ids.
+    Replace {
+        tasks: Vec<Task>,
+        at: DateTime<Utc>,
+    },
+}
+
+impl Event {
+    pub fn apply(&self, tasks: &mut Vec<Task>, notes: &mut String) {
+        let find = |tasks: &mut Vec<Task>, id: &Uuid| tasks.iter().position(|t| t.task_id == *id);
+
+        match self {
+            Event::Add { task } | Event::Reschedule { task } => tasks.push(task.clone()),
+            Event::CheckBox { task_id, done, at } => {
+                if let Some(i) = find(tasks, task_id) {
+                    tasks[i].done = *done;
+                    tasks[i].modified.done = *at;
+                }
+            }
+            Event::CycleTaskState { task_id, state, at } => {
+                if let Some(i) = find(tasks, task_id) {
+                    tasks[i].state = *state;
+                    tasks[i].modified.state = *at;
+                }
+            }
+            Event::Delete { task_id, .. } => {
+                if let Some(i) = find(tasks, task_id) {
+                    tasks.remove(i);
+                }
+            }
+            Event::EditDone { task_id, text, at } => {
+                if let Some(i) = find(tasks, task_id) {
+                    tasks[i].task_text = text.clone();
+                    tasks[i].modified.text = *at;
+                }
+            }
+            Event::Notes {
+                notes: new_notes, ..
+            } => *notes = new_notes.clone(),
+            Event::Reorder { task_ids, .. } => {
+                let position = |task: &Task| {
+                    let id = task_ids.iter().position(|id| *id == task.task_id);
+                    id.unwrap_or(task_ids.len())
+                };
+                tasks.sort_by_key(position);
+            }
+            Event::Replace {
+                tasks: new_tasks, ..
+            } => *tasks = new_tasks.clone(),
+        }
+    }
+}
+
+/// The events that turn `old` into `new`, as the edits making those
+/// changes one at a time would record them. Field events carry the time
+/// `new` stamps the field with; `at` dates deletions and reordering.
+pub fn changes(old: &[Task], new: &[Task], at: DateTime<Utc>) -> Vec<Event> {
+    let mut events: Vec<Event> = old
+        .iter()
+        .filter(|task| !new.iter().any(|t| t.task_id == task.task_id))
+        .map(|task| Event::Delete {
+            task_id: task.task_id,
+            at,
+        })
+        .collect();
+    for task in new {
+        let task_id = task.task_id;
+        let Some(before) = old.iter().find(|t| t.task_id == task_id) else {
+            events.push(Event::Add { task: task.clone() });
+            continue;
+        };
+        if (&before.task_text, before.modified.text) != (&task.task_text, task.modified.text) {
+            events.push(Event::EditDone {
+                task_id,
+                text: task.task_text.clone(),
+                at: task.modified.text,
+            });
+        }
+        if (before.done, before.modified.done) != (task.done, task.modified.done) {
+            events.push(Event::CheckBox {
+                task_id,
+                done: task.done,
+                at: task.modified.done,
+            });
+        }
+        if (before.state, before.modified.state) != (task.state, task.modified.state) {
+            events.push(Event::CycleTaskState {
+                task_id,
+                state: task.state,
+                at: task.modified.state,
+            });
+        }
+    }
+
+    // added tasks go last, so the order can still differ
+    let mut tasks = old.to_vec();
+    for event in &events {
+        event.apply(&mut tasks, &mut String::new());
+    }
+    if tasks != new {
+        let reorder = Event::Reorder {
+            task_ids: new.iter().map(|task| task.task_id).collect(),
+            at,
+        };
+        reorder.apply(&mut tasks, &mut String::new());
+        events.push(reorder);
+    }
+    if tasks != new {
+        events.push(Event::Replace {
+            tasks: new.to_vec(),
+            at,
+        });
+    }
+    events
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Snapshot {
+    /// Number of events, counted from the first, folded into this snapshot.
+    events: usize,
+    tasks: Vec<Task>,
+    notes: String,
+}
+
+/// First line of a log that compaction rewrote: how many events were
+/// folded into the snapshot and dropped from the log.
+#[derive(Serialize, Deserialize)]
+struct LogStart {
+    compacted: usize,
+}
+
+/// Append-only event log of a profile plus a periodic snapshot of the
+/// state it produces, so loading only replays events after the snapshot.
+pub struct Journal {
+    dir: PathBuf,
+    tasks: Vec<Task>,
+    notes: String,
+    events: usize,
+    snapshot_events: usize,
+}
+
+impl Journal {
+    fn new(dir: &Path) -> Journal {
+        Journal {
+            dir: dir.to_path_buf(),
+            tasks: vec![],
+            notes: String::new(),
+            events: 0,
+            snapshot_events: 0,
+        }
+    }
+
+    pub fn open(dir: &Path) -> Journal {
+        let mut journal = Journal::new(dir);
+        journal.reload();
+        journal
+    }
+
+    pub fn reload(&mut self) {
+        let snapshot =
+            std::fs::read_to_string(self.dir.join(SNAPSHOT_FILENAME)).unwrap_or_default();
+        let log = std::fs::read_to_string(self.dir.join(JOURNAL_FILENAME)).unwrap_or_default();
+        self.load(&snapshot, &log);
+    }
+
+    fn load(&mut self, snapshot: &str, log: &str) {
+        let snapshot: Snapshot = serde_json::from_str(snapshot).unwrap_or_default();
+
+        self.snapshot_events = snapshot.events;
+        self.events = 0;
+        let mut events = vec![];
+        for (i, line) in log.lines().enumerate() {
+            if i == 0
+                && let Ok(start) = serde_json::from_str::<LogStart>(line)
+            {
+                self.events = start.compacted;
+                continue;
+            }
+            self.events += 1;
+            // a crash between writing the snapshot and compacting the log
+            // leaves events the snapshot already holds
+            if self.events <= snapshot.events {
+                continue;
+            }
+            // a torn last line from a crash is skipped
+            if let Ok(event) = serde_json::from_str::<Event>(line) {
+                events.push(event);
+            }
+        }
+        (self.tasks, self.notes) = crate::replay(snapshot.tasks, snapshot.notes, events);
+    }
+
+    /// The tasks and notes produced by the given snapshot and journal
+    /// contents, e.g. as read from an older commit.
+    pub fn replay(snapshot: &str, log: &str) -> (Vec<Task>, String) {
+        let mut journal = Journal::new(Path::new(""));
+        journal.load(snapshot, log);
+        (journal.tasks, journal.notes)
+    }
+
+    fn push(&mut self, event: &Event) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(event).expect("failed to serialize");
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(JOURNAL_FILENAME))?
+            .write_all(line.as_bytes())?;
+
+        let tasks = std::mem::take(&mut self.tasks);
+        let notes = std::mem::take(&mut self.notes);
+        (self.tasks, self.notes) = crate::replay(tasks, notes, [event.clone()]);
+        self.events += 1;
+        if self.events - self.snapshot_events >= SNAPSHOT_EVERY {
+            self.snapshot()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the state to the snapshot, then drops the events it holds
+    /// from the log.
+    fn snapshot(&mut self) -> std::io::Result<()> {
+        let snapshot = Snapshot {
+            events: self.events,
+            tasks: self.tasks.clone(),
+            notes: self.notes.clone(),
+        };
+        let json = serde_json::to_string_pretty(&snapshot).expect("failed to serialize");
+        let path = self.dir.join(SNAPSHOT_FILENAME);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(tmp, path)?;
+        self.snapshot_events = self.events;
+
+        let mut start = serde_json::to_string(&LogStart {
+            compacted: self.events,
+        })
+        .expect("failed to serialize");
+        start.push('\n');
+        let path = self.dir.join(JOURNAL_FILENAME);
+        let tmp = path.with_extension("jsonl.tmp");
+        std::fs::write(&tmp, start)?;
+        std::fs::rename(tmp, path)
+    }
+}
+
+/// Saves only append an event when the events so far did not already
+/// produce exactly the saved state.
+impl Storage for Journal {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        self.reload();
+        Ok(self.tasks.clone())
+    }
+
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        if self.tasks == tasks {
+            return Ok(());
+        }
+        self.append(&Event::Replace {
+            tasks: tasks.to_vec(),
+            at: Utc::now(),
+        })
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        self.reload();
+        Ok(self.notes.clone())
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        if self.notes == notes {
+            return Ok(());
+        }
+        self.append(&Event::Notes {
+            notes: notes.to_string(),
+            at: Utc::now(),
+        })
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        storage::read_settings(&self.dir.join(SETTINGS_FILENAME))
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        storage::write_settings(&self.dir.join(SETTINGS_FILENAME), settings)
+    }
+
+    fn append(&mut self, event: &Event) -> Result<(), String> {
+        self.push(event).map_err(|e| format!("journal: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn add(text: &str) -> Event {
+        Event::Add {
+            task: Task::new(text.to_string(), TaskState::Normal),
+        }
+    }
+
+    #[test]
+    fn compaction_drops_the_snapshotted_events_from_the_log() {
+        let dir = temp_dir();
+        let mut journal = Journal::open(&dir);
+        for i in 0..SNAPSHOT_EVERY + 3 {
+            journal.append(&add(&i.to_string())).unwrap();
+        }
+
+        let log = std::fs::read_to_string(dir.join(JOURNAL_FILENAME)).unwrap();
+        assert_eq!(log.lines().count(), 1 + 3);
+        let reopened = Journal::open(&dir);
+        assert_eq!(reopened.events, SNAPSHOT_EVERY + 3);
+        assert!(reopened.tasks == journal.tasks);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn events_the_snapshot_holds_are_replayed_once() {
+        let events = [add("a"), add("b"), add("c")];
+        let log: String = events
+            .iter()
+            .map(|event| serde_json::to_string(event).unwrap() + "\n")
+            .collect();
+        let (tasks, _) = crate::replay(vec![], String::new(), events[..2].to_vec());
+        let snapshot = serde_json::to_string(&Snapshot {
+            events: 2,
+            tasks,
+            notes: String::new(),
+        })
+        .unwrap();
+
+        // as left by a crash between the snapshot and compacting the log
+        let (tasks, _) = Journal::replay(&snapshot, &log);
+        let texts: Vec<&str> = tasks.iter().map(|t| t.task_text.as_str()).collect();
+        assert_eq!(texts, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn changes_replay_to_the_new_tasks() {
+        let old: Vec<Task> = ["a", "b", "c"]
+            .into_iter()
+            .map(|text| Task::new(text.to_string(), TaskState::Normal))
+            .collect();
+        let later = Utc::now() + chrono::Duration::seconds(1);
+        let mut edited = old[2].clone();
+        edited.task_text = "c, edited".to_string();
+        edited.done = true;
+        edited.modified.text = later;
+        edited.modified.done = later;
+        let new = vec![
+            edited,
+            old[0].clone(),
+            Task::new("d".to_string(), TaskState::Chosen),
+        ];
+
+        let events = changes(&old, &new, Utc::now());
+        assert!(!events.iter().any(|e| matches!(e, Event::Replace { .. })));
+        let (tasks, _) = crate::replay(old, String::new(), events);
+        assert!(tasks == new);
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+pub const LOCK_FILENAME: &str = "cardamom-chai.lock";
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Status {
+    #[default]
+    Owned,
+    /// Another running instance holds the lock, with its pid when known.
+    HeldBy(Option<u32>),
+}
+
+/// Takes the single-instance lock for `dir`.
+///
+/// The lock is an OS advisory lock on a file that also records the owner's
+/// pid. The OS drops the lock when a process dies, so a lock file that can be
+/// locked again was left behind by a crash and is simply taken over.
+pub fn acquire(dir: &Path) -> Result<File, Status> {
+    let path = dir.join(LOCK_FILENAME);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|_| Status::HeldBy(None))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => return Err(Status::HeldBy(read_pid(&mut file))),
+        // locking is unsupported here (e.g. some network filesystems), so
+        // only the recorded pid can tell whether another instance runs
+        Err(TryLockError::Error(e)) => {
+            eprintln!("cardamom-chai: cannot lock {}: {e}", path.display());
+            if let Some(pid) = read_pid(&mut file)
+                && pid != std::process::id()
+                && is_running(pid)
+            {
+                return Err(Status::HeldBy(Some(pid)));
+            }
+        }
+    }
+
+    if let Some(pid) = read_pid(&mut file)
+        && pid != std::process::id()
+        && !is_running(pid)
+    {
+        eprintln!("cardamom-chai: clearing stale lock left by pid {pid}");
+    }
+    file.set_len(0).ok();
+    file.rewind().ok();
+    write!(file, "{}", std::process::id()).ok();
+    file.flush().ok();
+    Ok(file)
+}
+
+fn is_running(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+/// Gives up the lock, clearing the pid so the next owner does not report
+/// it as left behind by a crash.
+pub fn release(file: File) {
+    file.set_len(0).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn released_locks_leave_no_pid_behind() {
+        let dir = std::env::temp_dir().join(format!("cardamom-chai-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOCK_FILENAME);
+
+        let file = acquire(&dir).ok().unwrap();
+        let pid = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(pid, std::process::id().to_string());
+        release(file);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        assert!(acquire(&dir).is_ok());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,16 +1,101 @@
+mod cli;
+mod crdt;
+mod crypto;
+mod git;
+mod html;
+mod http;
+mod ics;
+mod ipc;
+mod journal;
+mod lock;
+mod markdown;
+mod merge;
+mod org;
+mod profiles;
+mod settings;
+mod sqlite;
+mod storage;
+mod summary;
+mod sync;
+mod table;
+mod taskwarrior;
+mod theme;
+mod todotxt;
+
+use chrono::{DateTime, Local, Utc};
+use clap::Parser;
 use dirs::data_dir;
 use eframe::egui::{self, RichText};
+use journal::Event;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use settings::{Backend, Settings};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use storage::Storage;
+use theme::Theme;
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
-#[derive(Default, Clone, Serialize, Deserialize)]
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Task {
     task_id: Uuid,
     task_text: String,
     done: bool,
     #[serde(default)]
     state: TaskState,
+    #[serde(default)]
+    modified: Modified,
+}
+
+/// When each field of a task was last changed, so copies of the database
+/// can be merged field by field.
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Modified {
+    text: DateTime<Utc>,
+    done: DateTime<Utc>,
+    state: DateTime<Utc>,
+}
+
+impl Task {
+    fn new(task_text: String, state: TaskState) -> Task {
+        let now = Utc::now();
+        Task {
+            task_id: Uuid::new_v4(),
+            task_text,
+            done: false,
+            state,
+            modified: Modified {
+                text: now,
+                done: now,
+                state: now,
+            },
+        }
+    }
+
+    /// A task for text typed into the add box: a trailing `!` or `*` makes
+    /// it chosen (the `*` is kept) and a trailing `?` uncertain.
+    fn quick_add(text: &str) -> Task {
+        let mut state = TaskState::Normal;
+        let mut text = text.trim().to_string();
+
+        if text.ends_with('?') {
+            state = TaskState::Uncertain;
+            text = text.trim_end_matches('?').to_string();
+        } else if text.ends_with('!') {
+            state = TaskState::Chosen;
+            text = text.trim_end_matches('!').to_string();
+        } else if text.ends_with('*') {
+            state = TaskState::Chosen;
+        }
+        Task::new(text, state)
+    }
+
+    fn set_text(&mut self, task_text: String) {
+        self.task_text = task_text;
+        self.modified.text = Utc::now();
+    }
 }
 
 #[derive(PartialEq, Default, Copy, Clone, Serialize, Deserialize)]
@@ -21,17 +106,34 @@ enum TaskState {
     Uncertain,
 }
 
-#[derive(PartialEq, Default, Copy, Clone)]
+#[derive(PartialEq, Default, Copy, Clone, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
 enum Filter {
     #[default]
     All,
     Active,
     Uncertain,
     Pending,
+    #[value(skip)]
     Search,
     Done,
 }
 
+impl Filter {
+    /// Whether `task` is shown; `search` is the search text without its
+    /// leading '/'.
+    fn shows(self, task: &Task, search: &str) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Active => matches!(task.state, TaskState::Chosen),
+            Filter::Pending => !task.done,
+            Filter::Uncertain => matches!(task.state, TaskState::Uncertain),
+            Filter::Search => fuzzy_match(&task.task_text.to_lowercase(), search),
+            Filter::Done => task.done,
+        }
+    }
+}
+
 #[derive(Default)]
 enum NotesState {
     #[default]
@@ -47,6 +149,133 @@ struct Model {
     edit_tasks: Vec<Uuid>,
     notes: String,
     notes_state: NotesState,
+    theme: Theme,
+    themes: Vec<String>,
+    settings: Settings,
+    /// `settings` as saved, without the session's `--set` and `--profile`
+    /// overrides.
+    saved_settings: Settings,
+    settings_edit: Option<Settings>,
+    profile: Option<String>,
+    /// Why the profile the settings name was not opened.
+    profile_status: Option<String>,
+    profiles: Vec<String>,
+    new_profile_text_box: String,
+    instance: lock::Status,
+    conflicts: Vec<merge::TaskConflict>,
+    notes_conflict: Option<String>,
+    history: Option<History>,
+    markdown: Option<MarkdownDialog>,
+    transfer: Option<TransferDialog>,
+    summary: Option<SummaryDialog>,
+    /// Tasks shown for the current filter, when the storage backend
+    /// answered it with a query.
+    matches: Option<HashSet<Uuid>>,
+    locked: Option<Locked>,
+    last_activity: Option<Instant>,
+    passphrase_change: PassphraseForm,
+    /// Whether the tasks and notes have been read from storage since
+    /// startup or the last profile switch or lock. Until then a change
+    /// would be saved over them.
+    loaded: bool,
+}
+
+/// The unlock screen shown instead of the tasks while encryption is on.
+struct Locked {
+    /// Whether the databases are already encrypted, as opposed to choosing
+    /// a first passphrase.
+    existing: bool,
+    form: PassphraseForm,
+}
+
+#[derive(Default, Clone)]
+struct PassphraseForm {
+    passphrase: String,
+    confirm: String,
+    error: Option<String>,
+    busy: bool,
+}
+
+impl PassphraseForm {
+    /// Marks the form busy and returns the passphrase, or records why it
+    /// cannot be used.
+    fn submit(&mut self, confirm: bool) -> Option<String> {
+        self.error = if self.passphrase.is_empty() {
+            Some("enter a passphrase".to_string())
+        } else if confirm && self.passphrase != self.confirm {
+            Some("passphrases do not match".to_string())
+        } else {
+            None
+        };
+        self.busy = self.error.is_none();
+        self.busy.then(|| self.passphrase.clone())
+    }
+}
+
+/// Copying tasks out as a Markdown checklist and pasting checklists in.
+#[derive(Clone)]
+struct MarkdownDialog {
+    grouping: markdown::Grouping,
+    paste: String,
+    /// The checklist file; relative paths are in the profile directory.
+    path: String,
+    /// The outcome of the last file import or export.
+    status: Option<String>,
+}
+
+impl Default for MarkdownDialog {
+    fn default() -> Self {
+        MarkdownDialog {
+            grouping: markdown::Grouping::default(),
+            paste: String::new(),
+            path: markdown::MARKDOWN_FILENAME.to_string(),
+            status: None,
+        }
+    }
+}
+
+/// Importing tasks from CSV or JSON files, and exporting them as such.
+#[derive(Clone)]
+struct TransferDialog {
+    format: table::Format,
+    /// Relative paths are in the profile directory.
+    path: String,
+    table: Option<table::Table>,
+    /// The field each column of `table` is read into.
+    mapping: Vec<table::Field>,
+    dedupe: table::Dedupe,
+    /// The outcome of the last read, import or export.
+    status: Option<String>,
+}
+
+impl Default for TransferDialog {
+    fn default() -> Self {
+        let format = table::Format::default();
+        TransferDialog {
+            format,
+            path: format.default_filename().to_string(),
+            table: None,
+            mapping: vec![],
+            dedupe: table::Dedupe::default(),
+            status: None,
+        }
+    }
+}
+
+/// A summary of a day or week, for pasting into a standup.
+#[derive(Default, Clone, PartialEq)]
+struct SummaryDialog {
+    period: summary::Period,
+    /// How many periods before the current one.
+    back: u32,
+}
+
+#[derive(Default)]
+struct History {
+    commits: Vec<git::Commit>,
+    selected: Option<String>,
+    tasks: Vec<Task>,
+    notes: String,
 }
 
 enum Msg {
@@ -63,151 +292,396 @@ enum Msg {
     EditDone(Uuid),
     LoadedTasks(Vec<Task>),
     LoadedNotes(String),
+    QueriedTasks(Filter, String, Vec<Uuid>),
     EditNote,
     EditNoteInput(String),
     EditNoteDone,
+    LoadedThemes(Vec<String>),
+    LoadedTheme(Theme),
+    SetTheme(String),
+    ExportTheme,
+    /// The settings as saved, and the session's `--set` and `--profile`
+    /// overrides for them.
+    LoadedSettings(Settings, Vec<String>),
+    OpenSettings,
+    SettingsInput(Settings),
+    SaveSettings,
+    CloseSettings,
+    LoadedProfiles(Vec<String>),
+    SetProfile(String),
+    ProfileSwitched(String),
+    NewProfileInput(String),
+    InstanceLock(lock::Status),
+    RetryLock,
+    TasksChangedOnDisk(Vec<Task>, Vec<Task>),
+    NotesChangedOnDisk(String, String),
+    ResolveTaskConflict(Uuid, merge::Side),
+    ResolveNotesConflict(merge::Side),
+    SyncConflicts(sync::ConflictCopies),
+    OpenHistory,
+    LoadedHistory(Vec<git::Commit>),
+    SelectCommit(String),
+    LoadedCommit(String, Vec<Task>, String),
+    RestoreCommit,
+    CloseHistory,
+    ImportJsonDatabase,
+    ImportedJsonDatabase(Vec<Task>, String),
+    ExportJsonDatabase,
+    OpenMarkdown,
+    MarkdownInput(MarkdownDialog),
+    ImportMarkdown,
+    ImportMarkdownFile,
+    /// The contents of the checklist file at the path.
+    ImportedMarkdown(String, Result<String, String>),
+    ExportMarkdownFile,
+    MarkdownStatus(String),
+    CloseMarkdown,
+    OpenTransfer,
+    TransferInput(TransferDialog),
+    ReadTable,
+    LoadedTable(Result<table::Table, String>),
+    /// The tasks read from the file at the path, in a format that keeps ids.
+    ImportedTasks(String, Result<Vec<Task>, String>),
+    ImportTable,
+    ExportTable,
+    TransferStatus(String),
+    CloseTransfer,
+    OpenSummary,
+    SummaryInput(SummaryDialog),
+    AppendSummary,
+    CloseSummary,
+    /// A request from another program, over the socket or the HTTP API,
+    /// answered with `Cmd::IpcReply`.
+    IpcRequest(Uuid, ipc::Request),
+    /// A journaled event, replayed to rebuild the state it produced.
+    Replay(Event),
+    Locked(bool),
+    UnlockInput(PassphraseForm),
+    Unlock,
+    Unlocked(Result<crypto::Key, String>),
+    LockNow,
+    Activity,
+    PassphraseChangeInput(PassphraseForm),
+    ChangePassphrase,
+    PassphraseChanged(Result<crypto::Key, String>),
 }
 
 fn init() -> (Model, Vec<Cmd>) {
     (
         Model::default(),
-        vec![Cmd::LoadTasks, Cmd::LoadNotes, Cmd::InitTheme],
+        vec![
+            Cmd::AcquireLock,
+            Cmd::ApplyTheme(Theme::default()),
+            Cmd::LoadThemes,
+            Cmd::LoadSettings,
+            Cmd::LoadProfiles,
+        ],
     )
 }
 
 fn update(m: Model, msg: Msg) -> (Model, Vec<Cmd>) {
+    let history_message = history_message(&m, &msg);
+    let shown = (m.filter, m.add_task_text_box.clone());
+    let loaded = matches!(msg, Msg::LoadedTasks(_));
+    let (mut m, mut cmds) = update_model(m, msg);
+    let written = cmds.iter().any(|cmd| matches!(cmd, Cmd::WriteTasks(_)));
+    if cmds
+        .iter()
+        .any(|cmd| matches!(cmd, Cmd::WriteTasks(_) | Cmd::WriteNotes(_)))
+    {
+        cmds.push(Cmd::RecordHistory(history_message));
+    }
+
+    // SQLite answers filters and searches with indexed queries
+    if m.settings.storage == Backend::Sqlite && m.filter != Filter::All {
+        let search = m.add_task_text_box.trim_start_matches('/').to_string();
+        let reshown =
+            shown.0 != m.filter || (m.filter == Filter::Search && shown.1 != m.add_task_text_box);
+        if reshown {
+            m.matches = None;
+        }
+        if reshown || written || loaded {
+            cmds.push(Cmd::QueryTasks(m.filter, search));
+        }
+    } else {
+        m.matches = None;
+    }
+    (m, cmds)
+}
+
+/// One line describing the change `msg` makes, used as a git commit message.
+fn history_message(m: &Model, msg: &Msg) -> String {
+    let text = |id: &Uuid| {
+        m.tasks
+            .iter()
+            .find(|t| t.task_id == *id)
+            .map(|t| t.task_text.clone())
+            .unwrap_or_default()
+    };
+
     match msg {
-        Msg::LoadedTasks(tasks) => (Model { tasks, ..m }, vec![]),
-        Msg::LoadedNotes(notes) => (Model { notes, ..m }, vec![]),
-        Msg::TextInput(task_text) => (
-            Model {
-                add_task_text_box: task_text,
+        Msg::Add => format!("Add \"{}\"", m.add_task_text_box.trim()),
+        Msg::CheckBox(id, true) => format!("Complete \"{}\"", text(id)),
+        Msg::CheckBox(id, false) => format!("Reopen \"{}\"", text(id)),
+        Msg::Delete(id) => format!("Delete \"{}\"", text(id)),
+        Msg::CycleTaskState(id) => format!("Change state of \"{}\"", text(id)),
+        Msg::Reschedule(text) | Msg::RescheduleActive(text) => {
+            format!("Reschedule \"{text}\"")
+        }
+        Msg::EditDone(id) => format!("Edit \"{}\"", text(id)),
+        Msg::EditNoteDone => "Edit notes".to_string(),
+        Msg::AppendSummary => "Append summary to notes".to_string(),
+        Msg::IpcRequest(_, ipc::Request::Add { text }) => format!("Add \"{}\"", text.trim()),
+        Msg::IpcRequest(_, ipc::Request::Complete { task_id }) => {
+            match find_task(&m.tasks, task_id) {
+                Ok(task) => format!("Complete \"{}\"", task.task_text),
+                Err(_) => "Complete".to_string(),
+            }
+        }
+        Msg::IpcRequest(_, ipc::Request::Update { task_id, .. }) => {
+            match find_task(&m.tasks, task_id) {
+                Ok(task) => format!("Edit \"{}\"", task.task_text),
+                Err(_) => "Edit".to_string(),
+            }
+        }
+        Msg::IpcRequest(_, ipc::Request::Delete { task_id }) => {
+            match find_task(&m.tasks, task_id) {
+                Ok(task) => format!("Delete \"{}\"", task.task_text),
+                Err(_) => "Delete".to_string(),
+            }
+        }
+        Msg::IpcRequest(_, ipc::Request::AppendNote { .. }) => "Append to notes".to_string(),
+        Msg::IpcRequest(_, ipc::Request::SetNotes { .. }) => "Edit notes".to_string(),
+        Msg::TasksChangedOnDisk(..) | Msg::NotesChangedOnDisk(..) => {
+            "Merge changes made on disk".to_string()
+        }
+        Msg::ResolveTaskConflict(..) | Msg::ResolveNotesConflict(_) => {
+            "Resolve conflict".to_string()
+        }
+        Msg::SyncConflicts(_) => "Merge sync conflict copies".to_string(),
+        Msg::ImportedJsonDatabase(..) => format!("Import {TASK_DATABASE_FILENAME}"),
+        Msg::ImportedTasks(path, _) => format!("Import {path}"),
+        Msg::ImportMarkdown => "Import Markdown checklist".to_string(),
+        Msg::ImportedMarkdown(path, _) => format!("Import {path}"),
+        Msg::ImportTable => match &m.transfer {
+            Some(dialog) => format!("Import {}", dialog.path),
+            None => "Import".to_string(),
+        },
+        Msg::LockNow => "Save before locking".to_string(),
+        Msg::PassphraseChanged(_) => "Change passphrase".to_string(),
+        Msg::SetProfile(_) | Msg::LoadedSettings(..) | Msg::SaveSettings => {
+            "Save before switching profile".to_string()
+        }
+        Msg::RestoreCommit => match m.history.as_ref().and_then(|h| h.selected.as_ref()) {
+            Some(hash) => format!("Restore {}", &hash[..hash.len().min(8)]),
+            None => "Restore".to_string(),
+        },
+        _ => "Update".to_string(),
+    }
+}
 
-                ..m
-            },
-            vec![],
-        ),
+/// Applies a journaled change to the model and persists the result.
+fn record(m: Model, event: Event) -> (Model, Vec<Cmd>) {
+    let mut tasks = m.tasks;
+    let mut notes = m.notes;
+    event.apply(&mut tasks, &mut notes);
 
-        Msg::Add => {
-            let mut tasks = m.tasks;
+    let write = match event {
+        Event::Notes { .. } => Cmd::WriteNotes(notes.clone()),
+        _ => Cmd::WriteTasks(tasks.clone()),
+    };
+    (
+        Model { tasks, notes, ..m },
+        vec![Cmd::AppendEvent(event), write],
+    )
+}
 
-            let mut state = TaskState::Normal;
-            let mut text = m.add_task_text_box.trim().to_string();
+/// Records the change from the model's tasks and notes to `tasks` and
+/// `notes` as the events single edits would make, so merges and imports
+/// are journaled like any other change. Each kind is written once.
+fn record_changes(m: Model, tasks: Vec<Task>, notes: String) -> (Model, Vec<Cmd>) {
+    let now = Utc::now();
+    let mut events = journal::changes(&m.tasks, &tasks, now);
+    let writes_tasks = !events.is_empty();
+    let writes_notes = notes != m.notes;
+    if writes_notes {
+        events.push(Event::Notes { notes, at: now });
+    }
 
-            if text.ends_with('?') {
-                state = TaskState::Uncertain;
-                text = text.trim_end_matches('?').to_string();
-            } else if text.ends_with('!') {
-                state = TaskState::Chosen;
-                text = text.trim_end_matches('!').to_string();
-            } else if text.ends_with('*') {
-                state = TaskState::Chosen;
-            }
+    let mut m = m;
+    let mut cmds = vec![];
+    for event in events {
+        m = record(m, event.clone()).0;
+        cmds.push(Cmd::AppendEvent(event));
+    }
+    if writes_tasks {
+        cmds.push(Cmd::WriteTasks(m.tasks.clone()));
+    }
+    if writes_notes {
+        cmds.push(Cmd::WriteNotes(m.notes.clone()));
+    }
+    (m, cmds)
+}
 
-            tasks.push(Task {
-                task_id: Uuid::new_v4(),
-                task_text: text,
-                state,
-                ..Default::default()
-            });
+/// The tasks and notes `events` lead to from `tasks` and `notes`, replayed
+/// through `update` like the edits that recorded them.
+fn replay(
+    tasks: Vec<Task>,
+    notes: String,
+    events: impl IntoIterator<Item = Event>,
+) -> (Vec<Task>, String) {
+    let m = Model {
+        tasks,
+        notes,
+        ..Model::default()
+    };
+    let m = events
+        .into_iter()
+        .fold(m, |m, event| update(m, Msg::Replay(event)).0);
+    (m.tasks, m.notes)
+}
 
-            (
-                Model {
-                    tasks: tasks.clone(),
-                    add_task_text_box: "".to_string(),
-                    ..m
-                },
-                vec![Cmd::WriteTasks(tasks)],
-            )
+/// `tasks` with `imported` added; imported tasks replace stored ones with
+/// the same id.
+fn import_tasks(mut tasks: Vec<Task>, imported: Vec<Task>) -> Vec<Task> {
+    for task in imported {
+        match tasks.iter_mut().find(|t| t.task_id == task.task_id) {
+            Some(existing) => *existing = task,
+            None => tasks.push(task),
         }
+    }
+    tasks
+}
 
-        Msg::Reschedule(text) => {
-            let mut tasks = m.tasks;
-            tasks.push(Task {
-                task_id: Uuid::new_v4(),
-                task_text: text,
-                ..Default::default()
-            });
+/// The task whose id starts with `id_prefix`, which must pick out one.
+fn find_task<'a>(tasks: &'a [Task], id_prefix: &str) -> Result<&'a Task, String> {
+    let prefix = id_prefix.trim().to_lowercase();
+    let found: Vec<&Task> = tasks
+        .iter()
+        .filter(|task| !prefix.is_empty() && task.task_id.to_string().starts_with(&prefix))
+        .collect();
+    match found.as_slice() {
+        [task] => Ok(task),
+        [] => Err(format!("no task id starts with {id_prefix}")),
+        _ => Err(format!("{} task ids start with {id_prefix}", found.len())),
+    }
+}
 
+/// `notes` with `text` added as a paragraph of its own.
+fn append_paragraph(notes: &str, text: &str) -> String {
+    match notes.trim_end() {
+        "" => text.to_string(),
+        notes => format!("{notes}\n\n{text}"),
+    }
+}
+
+fn update_model(m: Model, msg: Msg) -> (Model, Vec<Cmd>) {
+    match msg {
+        Msg::LoadedTasks(tasks) => (Model { tasks, ..m }, vec![]),
+        // the notes are loaded after the tasks
+        Msg::LoadedNotes(notes) => (
+            Model {
+                notes,
+                loaded: true,
+                ..m
+            },
+            vec![],
+        ),
+        Msg::QueriedTasks(filter, search, ids) => {
+            // drop answers to a filter or search that is no longer shown
+            if filter != m.filter
+                || (filter == Filter::Search
+                    && search != m.add_task_text_box.trim_start_matches('/'))
+            {
+                return (m, vec![]);
+            }
             (
                 Model {
-                    tasks: tasks.clone(),
+                    matches: Some(ids.into_iter().collect()),
                     ..m
                 },
-                vec![Cmd::WriteTasks(tasks)],
+                vec![],
             )
         }
+        Msg::TextInput(task_text) => (
+            Model {
+                add_task_text_box: task_text,
 
-        Msg::RescheduleActive(text) => {
-            let mut tasks = m.tasks;
-            tasks.push(Task {
-                task_id: Uuid::new_v4(),
-                task_text: text,
-                state: TaskState::Chosen,
-                ..Default::default()
-            });
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::Add => {
+            let task = Task::quick_add(&m.add_task_text_box);
+            let (m, cmds) = record(m, Event::Add { task });
 
             (
                 Model {
-                    tasks: tasks.clone(),
+                    add_task_text_box: "".to_string(),
                     ..m
                 },
-                vec![Cmd::WriteTasks(tasks)],
+                cmds,
             )
         }
 
-        Msg::CheckBox(id, done) => {
-            let mut tasks = m.tasks;
-            if let Some(task) = tasks.iter_mut().find(|t| t.task_id == id) {
-                task.done = done;
-            }
+        Msg::Reschedule(text) => record(
+            m,
+            Event::Reschedule {
+                task: Task::new(text, TaskState::Normal),
+            },
+        ),
 
-            (
-                Model {
-                    tasks: tasks.clone(),
-                    ..m
-                },
-                vec![Cmd::WriteTasks(tasks)],
-            )
-        }
+        Msg::RescheduleActive(text) => record(
+            m,
+            Event::Reschedule {
+                task: Task::new(text, TaskState::Chosen),
+            },
+        ),
+
+        Msg::CheckBox(id, done) => record(
+            m,
+            Event::CheckBox {
+                task_id: id,
+                done,
+                at: Utc::now(),
+            },
+        ),
 
         Msg::CycleTaskState(id) => {
-            let mut tasks = m.tasks;
-            if let Some(task) = tasks.iter_mut().find(|t| t.task_id == id) {
+            let state = m.tasks.iter().find(|t| t.task_id == id).map(|task| {
                 if task.done {
-                    task.state = TaskState::Normal;
+                    TaskState::Normal
                 } else {
-                    task.state = match task.state {
+                    match task.state {
                         TaskState::Normal => TaskState::Chosen,
                         TaskState::Chosen => TaskState::Uncertain,
                         TaskState::Uncertain => TaskState::Normal,
                     }
                 }
-            }
-
-            (
-                Model {
-                    tasks: tasks.clone(),
-                    ..m
-                },
-                vec![Cmd::WriteTasks(tasks)],
-            )
-        }
+            });
 
-        Msg::Delete(id) => {
-            let mut tasks = m.tasks;
-            if let Some(task) = tasks.iter().position(|t| t.task_id == id) {
-                tasks.remove(task);
+            match state {
+                Some(state) => record(
+                    m,
+                    Event::CycleTaskState {
+                        task_id: id,
+                        state,
+                        at: Utc::now(),
+                    },
+                ),
+                None => (m, vec![]),
             }
-            (
-                Model {
-                    tasks: tasks.clone(),
-                    ..m
-                },
-                vec![Cmd::WriteTasks(tasks)],
-            )
         }
 
+        Msg::Delete(id) => record(
+            m,
+            Event::Delete {
+                task_id: id,
+                at: Utc::now(),
+            },
+        ),
+
         Msg::SetFilter(filter) => (Model { filter, ..m }, vec![]),
 
         Msg::Edit(id) => {
@@ -219,7 +693,7 @@ fn update(m: Model, msg: Msg) -> (Model, Vec<Cmd>) {
         Msg::EditInput(id, new_text) => {
             let mut tasks = m.tasks;
             if let Some(task) = tasks.iter_mut().find(|t| t.task_id == id) {
-                task.task_text = new_text;
+                task.set_text(new_text);
             }
 
             (Model { tasks, ..m }, vec![])
@@ -230,9 +704,24 @@ fn update(m: Model, msg: Msg) -> (Model, Vec<Cmd>) {
             if let Some(edit_task) = edit_tasks.iter().position(|t| *t == id) {
                 edit_tasks.remove(edit_task);
             }
-            let tasks = m.tasks.clone();
+            let text = m
+                .tasks
+                .iter()
+                .find(|t| t.task_id == id)
+                .map(|t| t.task_text.clone());
+            let m = Model { edit_tasks, ..m };
 
-            (Model { edit_tasks, ..m }, vec![Cmd::WriteTasks(tasks)])
+            match text {
+                Some(text) => record(
+                    m,
+                    Event::EditDone {
+                        task_id: id,
+                        text,
+                        at: Utc::now(),
+                    },
+                ),
+                None => (m, vec![]),
+            }
         }
 
         Msg::EditNote => (
@@ -247,84 +736,1282 @@ fn update(m: Model, msg: Msg) -> (Model, Vec<Cmd>) {
 
         Msg::EditNoteDone => {
             let notes = m.notes.clone();
-            (
+            record(
                 Model {
                     notes_state: NotesState::Display,
                     ..m
                 },
-                vec![Cmd::WriteNotes(notes)],
+                Event::Notes {
+                    notes,
+                    at: Utc::now(),
+                },
             )
         }
-    }
-}
 
-fn view(ctx: &egui::Context, m: &Model, tx: &mut Vec<Msg>) {
-    egui::SidePanel::left("left_panel")
-        .resizable(true)
-        .default_width(350.0)
-        .width_range(80.0..=350.0)
-        .show(ctx, |_ui| {});
+        Msg::LoadedThemes(themes) => (Model { themes, ..m }, vec![]),
 
-    egui::SidePanel::right("right_panel")
-        .resizable(true)
-        .default_width(300.0)
-        .width_range(80.0..=350.0)
-        .show(ctx, |ui| {
-            ui.add_space(10.0);
-            ui.take_available_space();
-            let response = ui.label(RichText::new("Notes").strong().size(17.0));
-            if response.middle_clicked() {
-                match m.notes_state {
-                    NotesState::Display => tx.push(Msg::EditNote),
-                    NotesState::Edit => tx.push(Msg::EditNoteDone),
-                }
+        Msg::LoadedTheme(theme) => (
+            Model {
+                theme: theme.clone(),
+                ..m
+            },
+            vec![Cmd::ApplyTheme(theme)],
+        ),
+
+        Msg::LoadedProfiles(profiles) => (Model { profiles, ..m }, vec![]),
+
+        Msg::NewProfileInput(new_profile_text_box) => (
+            Model {
+                new_profile_text_box,
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::SetProfile(profile) => {
+            if !profiles::is_valid_name(&profile) || m.profile.as_ref() == Some(&profile) {
+                return (m, vec![]);
             }
-            egui::ScrollArea::vertical()
-                .auto_shrink(false)
-                .show(ui, |ui| match m.notes_state {
-                    NotesState::Display => {
-                        let mut cache = egui_commonmark::CommonMarkCache::default();
-                        egui_commonmark::CommonMarkViewer::new().show(ui, &mut cache, &m.notes);
-                    }
+            let settings = Settings {
+                profile: profile.clone(),
+                ..m.settings.clone()
+            };
+            let saved_settings = Settings {
+                profile,
+                ..m.saved_settings.clone()
+            };
+            let (m, mut cmds) = switch_profile(m, settings.profile.clone());
+            cmds.push(Cmd::WriteSettings(saved_settings.clone()));
+            (
+                Model {
+                    settings,
+                    saved_settings,
+                    profile_status: None,
+                    new_profile_text_box: "".to_string(),
+                    ..m
+                },
+                cmds,
+            )
+        }
 
-                    NotesState::Edit => {
-                        let mut notes_text_box = m.notes.clone();
-                        let response = ui.add(egui::TextEdit::multiline(&mut notes_text_box));
+        Msg::ProfileSwitched(profile) => {
+            // encrypted profiles load once the unlock screen has the key
+            let cmds = if m.settings.encryption {
+                vec![Cmd::Lock]
+            } else {
+                vec![Cmd::LoadTasks, Cmd::LoadNotes, Cmd::WatchDatabase]
+            };
+            (
+                Model {
+                    profile: Some(profile),
+                    ..m
+                },
+                cmds,
+            )
+        }
 
-                        if response.changed() {
-                            tx.push(Msg::EditNoteInput(notes_text_box));
-                        }
+        Msg::Locked(existing) => (
+            Model {
+                locked: Some(Locked {
+                    existing,
+                    form: PassphraseForm::default(),
+                }),
+                ..m
+            },
+            vec![],
+        ),
 
-                        if response.has_focus() {
-                            ui.input(|i| {
-                                if i.key_pressed(egui::Key::Enter) && i.modifiers.ctrl {
-                                    tx.push(Msg::EditNoteDone);
-                                }
-                            });
-                        }
+        Msg::UnlockInput(form) => match m.locked {
+            Some(locked) if !locked.form.busy => (
+                Model {
+                    locked: Some(Locked { form, ..locked }),
+                    ..m
+                },
+                vec![],
+            ),
+            locked => (Model { locked, ..m }, vec![]),
+        },
 
-                        if ui.button("save").clicked() {
-                            tx.push(Msg::EditNoteDone);
-                        }
-                    }
-                });
-        });
+        Msg::Unlock => match m.locked {
+            Some(mut locked) if !locked.form.busy => {
+                let cmds = match locked.form.submit(!locked.existing) {
+                    Some(passphrase) => vec![Cmd::Unlock(passphrase)],
+                    None => vec![],
+                };
+                (
+                    Model {
+                        locked: Some(locked),
+                        ..m
+                    },
+                    cmds,
+                )
+            }
+            locked => (Model { locked, ..m }, vec![]),
+        },
 
-    egui::TopBottomPanel::bottom("bottom_panel")
-        .resizable(false)
-        .min_height(0.0)
-        .show(ctx, |ui| {
-            ui.add_space(5.0);
-            ui.horizontal(|ui| {
-                let mut filter = m.filter;
-                let mut changed = false;
+        Msg::Unlocked(Ok(key)) => (
+            Model {
+                locked: None,
+                last_activity: Some(Instant::now()),
+                ..m
+            },
+            vec![
+                Cmd::UseKey(key),
+                Cmd::LoadTasks,
+                Cmd::LoadNotes,
+                Cmd::WatchDatabase,
+            ],
+        ),
 
-                changed |= ui
-                    .selectable_value(&mut filter, Filter::All, "All")
-                    .changed();
-                changed |= ui
-                    .selectable_value(&mut filter, Filter::Active, "Active")
-                    .changed();
+        Msg::Unlocked(Err(error)) => {
+            let locked = m.locked.map(|locked| Locked {
+                form: PassphraseForm {
+                    error: Some(error),
+                    ..PassphraseForm::default()
+                },
+                ..locked
+            });
+            (Model { locked, ..m }, vec![])
+        }
+
+        Msg::LockNow if m.settings.encryption && m.locked.is_none() => lock(m),
+        Msg::LockNow => (m, vec![]),
+
+        Msg::Activity => (
+            Model {
+                last_activity: Some(Instant::now()),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::PassphraseChangeInput(passphrase_change) if !m.passphrase_change.busy => (
+            Model {
+                passphrase_change,
+                ..m
+            },
+            vec![],
+        ),
+        Msg::PassphraseChangeInput(_) => (m, vec![]),
+
+        Msg::ChangePassphrase if !m.passphrase_change.busy => {
+            let mut passphrase_change = m.passphrase_change;
+            let cmds = match passphrase_change.submit(true) {
+                Some(passphrase) => vec![Cmd::ChangePassphrase(passphrase)],
+                None => vec![],
+            };
+            (
+                Model {
+                    passphrase_change,
+                    ..m
+                },
+                cmds,
+            )
+        }
+        Msg::ChangePassphrase => (m, vec![]),
+
+        Msg::PassphraseChanged(Ok(key)) => {
+            // writing everything again re-encrypts it with the new key
+            let cmds = vec![
+                Cmd::UseKey(key),
+                Cmd::WriteTasks(m.tasks.clone()),
+                Cmd::WriteNotes(m.notes.clone()),
+            ];
+            (
+                Model {
+                    passphrase_change: PassphraseForm::default(),
+                    ..m
+                },
+                cmds,
+            )
+        }
+
+        Msg::PassphraseChanged(Err(error)) => (
+            Model {
+                passphrase_change: PassphraseForm {
+                    error: Some(error),
+                    ..PassphraseForm::default()
+                },
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::InstanceLock(instance) => {
+            // the other instance may have saved changes while we were read-only
+            let reload = instance == lock::Status::Owned && m.instance != instance;
+            let cmds = if reload {
+                vec![
+                    Cmd::LoadTasks,
+                    Cmd::LoadNotes,
+                    Cmd::UseHttpApi(m.settings.http_api, m.settings.http_port),
+                ]
+            } else {
+                vec![]
+            };
+            (
+                Model {
+                    instance,
+                    loaded: m.loaded && !reload,
+                    ..m
+                },
+                cmds,
+            )
+        }
+
+        Msg::RetryLock => (m, vec![Cmd::AcquireLock]),
+
+        Msg::TasksChangedOnDisk(base, theirs) => {
+            let merged = merge::merge_tasks(&base, &m.tasks, &theirs);
+            let mut conflicts = m.conflicts.clone();
+            conflicts.retain(|c| !merged.conflicts.iter().any(|n| n.task_id == c.task_id));
+            conflicts.extend(merged.conflicts);
+
+            // unfinished edits are written by EditDone instead
+            if !m.edit_tasks.is_empty() {
+                return (
+                    Model {
+                        tasks: merged.tasks,
+                        conflicts,
+                        ..m
+                    },
+                    vec![],
+                );
+            }
+            // the changes are recorded against what is on disk
+            let notes = m.notes.clone();
+            let (m, cmds) = record_changes(Model { tasks: theirs, ..m }, merged.tasks, notes);
+            (Model { conflicts, ..m }, cmds)
+        }
+
+        Msg::NotesChangedOnDisk(base, theirs) => {
+            if m.notes == base || m.notes == theirs {
+                (
+                    Model {
+                        notes: theirs,
+                        notes_conflict: None,
+                        ..m
+                    },
+                    vec![],
+                )
+            } else {
+                (
+                    Model {
+                        notes_conflict: Some(theirs),
+                        ..m
+                    },
+                    vec![],
+                )
+            }
+        }
+
+        Msg::SyncConflicts(copies) => {
+            // leave the copies to the instance that owns the data dir
+            if m.instance != lock::Status::Owned {
+                return (m, vec![]);
+            }
+            let mut tasks = m.tasks.clone();
+            let mut notes = m.notes.clone();
+            let report = sync::merge_copies(&mut tasks, &mut notes, &copies);
+
+            // notes being edited are written by EditNoteDone instead
+            let (m, mut cmds) = if matches!(m.notes_state, NotesState::Display) {
+                record_changes(m, tasks, notes)
+            } else {
+                let current = m.notes.clone();
+                let (m, cmds) = record_changes(m, tasks, current);
+                (Model { notes, ..m }, cmds)
+            };
+            cmds.push(Cmd::FinishSyncMerge(copies.paths(), report));
+            (m, cmds)
+        }
+
+        Msg::OpenHistory => (
+            Model {
+                history: Some(History::default()),
+                ..m
+            },
+            vec![Cmd::LoadHistory],
+        ),
+
+        Msg::LoadedHistory(commits) => {
+            let history = m.history.map(|h| History { commits, ..h });
+            (Model { history, ..m }, vec![])
+        }
+
+        Msg::SelectCommit(hash) => {
+            let history = m.history.map(|h| History {
+                selected: Some(hash.clone()),
+                tasks: vec![],
+                notes: "".to_string(),
+                ..h
+            });
+            (Model { history, ..m }, vec![Cmd::LoadCommit(hash)])
+        }
+
+        Msg::LoadedCommit(hash, tasks, notes) => match m.history {
+            Some(history) if history.selected.as_ref() == Some(&hash) => (
+                Model {
+                    history: Some(History {
+                        tasks,
+                        notes,
+                        ..history
+                    }),
+                    ..m
+                },
+                vec![],
+            ),
+            history => (Model { history, ..m }, vec![]),
+        },
+
+        Msg::RestoreCommit => match m.history {
+            Some(history) if history.selected.is_some() => {
+                // restored fields count as new edits when copies are merged
+                let now = Utc::now();
+                let tasks: Vec<Task> = history
+                    .tasks
+                    .iter()
+                    .cloned()
+                    .map(|task| Task {
+                        modified: Modified {
+                            text: now,
+                            done: now,
+                            state: now,
+                        },
+                        ..task
+                    })
+                    .collect();
+                let notes = history.notes.clone();
+                let m = Model {
+                    edit_tasks: vec![],
+                    notes_state: NotesState::Display,
+                    history: None,
+                    ..m
+                };
+                record_changes(m, tasks, notes)
+            }
+            history => (Model { history, ..m }, vec![]),
+        },
+
+        Msg::CloseHistory => (Model { history: None, ..m }, vec![]),
+
+        Msg::ImportJsonDatabase if m.settings.storage != Backend::Json => {
+            (m, vec![Cmd::ImportJsonDatabase])
+        }
+
+        Msg::ImportedJsonDatabase(imported, imported_notes) => {
+            let tasks = import_tasks(m.tasks.clone(), imported);
+            let notes = if m.notes.is_empty() || m.notes == imported_notes {
+                imported_notes
+            } else if imported_notes.is_empty() {
+                m.notes.clone()
+            } else {
+                merge::merge_notes(&m.notes, &imported_notes)
+            };
+            record_changes(m, tasks, notes)
+        }
+
+        Msg::ExportJsonDatabase if m.settings.storage != Backend::Json => {
+            let cmds = vec![Cmd::ExportJsonDatabase(m.tasks.clone(), m.notes.clone())];
+            (m, cmds)
+        }
+
+        Msg::ImportJsonDatabase | Msg::ExportJsonDatabase => (m, vec![]),
+
+        Msg::OpenMarkdown => (
+            Model {
+                markdown: Some(MarkdownDialog::default()),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::MarkdownInput(dialog) => (
+            Model {
+                markdown: Some(dialog),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::ImportMarkdown => {
+            let Some(dialog) = m.markdown.clone() else {
+                return (m, vec![]);
+            };
+            let tasks = import_tasks(m.tasks.clone(), markdown::import(&dialog.paste));
+            let notes = m.notes.clone();
+            let m = Model {
+                markdown: Some(MarkdownDialog {
+                    paste: String::new(),
+                    ..dialog
+                }),
+                ..m
+            };
+            record_changes(m, tasks, notes)
+        }
+
+        Msg::ImportMarkdownFile if m.settings.storage != Backend::Markdown => {
+            let cmds = match &m.markdown {
+                Some(dialog) => vec![Cmd::ImportMarkdownFile(dialog.path.clone())],
+                None => vec![],
+            };
+            (m, cmds)
+        }
+
+        Msg::ImportedMarkdown(path, result) => {
+            let (imported, status) = match result {
+                Ok(text) => {
+                    let imported = markdown::import(&text);
+                    let status = format!("Imported {} tasks from {path}.", imported.len());
+                    (imported, status)
+                }
+                Err(e) => (vec![], e),
+            };
+            let tasks = import_tasks(m.tasks.clone(), imported);
+            let notes = m.notes.clone();
+            let markdown = m.markdown.map(|dialog| MarkdownDialog {
+                status: Some(status),
+                ..dialog
+            });
+            record_changes(Model { markdown, ..m }, tasks, notes)
+        }
+
+        Msg::ExportMarkdownFile if m.settings.storage != Backend::Markdown => {
+            let cmds = match &m.markdown {
+                Some(dialog) => {
+                    let text = markdown::export(&m.tasks, dialog.grouping);
+                    let path = dialog.path.clone();
+                    vec![Cmd::ExportMarkdownFile(path, text, m.tasks.len())]
+                }
+                None => vec![],
+            };
+            (m, cmds)
+        }
+
+        Msg::ImportMarkdownFile | Msg::ExportMarkdownFile => (m, vec![]),
+
+        Msg::MarkdownStatus(status) => {
+            let markdown = m.markdown.map(|dialog| MarkdownDialog {
+                status: Some(status),
+                ..dialog
+            });
+            (Model { markdown, ..m }, vec![])
+        }
+
+        Msg::CloseMarkdown => (
+            Model {
+                markdown: None,
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::OpenTransfer => (
+            Model {
+                transfer: Some(TransferDialog::default()),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::TransferInput(dialog) => (
+            Model {
+                transfer: Some(dialog),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::ReadTable => {
+            let cmds = match &m.transfer {
+                Some(dialog) if dialog.format.keeps_ids() => {
+                    vec![Cmd::ReadTasks(dialog.format, dialog.path.clone())]
+                }
+                Some(dialog) => vec![Cmd::ReadTable(dialog.format, dialog.path.clone())],
+                None => vec![],
+            };
+            (m, cmds)
+        }
+
+        Msg::ImportedTasks(path, result) => {
+            let Some(dialog) = m.transfer.clone() else {
+                return (m, vec![]);
+            };
+            let (tasks, status) = match result {
+                Ok(imported) => {
+                    let status = format!("Imported {} tasks from {path}.", imported.len());
+                    (import_tasks(m.tasks.clone(), imported), status)
+                }
+                Err(e) => (m.tasks.clone(), e),
+            };
+            let notes = m.notes.clone();
+            let m = Model {
+                transfer: Some(TransferDialog {
+                    table: None,
+                    mapping: vec![],
+                    status: Some(status),
+                    ..dialog
+                }),
+                ..m
+            };
+            record_changes(m, tasks, notes)
+        }
+
+        Msg::LoadedTable(result) => {
+            let Some(dialog) = m.transfer.clone() else {
+                return (m, vec![]);
+            };
+            let dialog = match result {
+                Ok(table) => {
+                    let mapping = table.guess_mapping();
+                    // rows with ids keep them, so match on those
+                    let dedupe = if mapping.contains(&table::Field::TaskId) {
+                        table::Dedupe::TaskId
+                    } else {
+                        dialog.dedupe
+                    };
+                    TransferDialog {
+                        mapping,
+                        dedupe,
+                        table: Some(table),
+                        status: None,
+                        ..dialog
+                    }
+                }
+                Err(e) => TransferDialog {
+                    table: None,
+                    mapping: vec![],
+                    status: Some(e),
+                    ..dialog
+                },
+            };
+            (
+                Model {
+                    transfer: Some(dialog),
+                    ..m
+                },
+                vec![],
+            )
+        }
+
+        Msg::ImportTable => {
+            let Some(dialog) = m.transfer.clone() else {
+                return (m, vec![]);
+            };
+            let Some(rows) = &dialog.table else {
+                return (m, vec![]);
+            };
+            let plan = table::plan(rows, &dialog.mapping, dialog.dedupe, &m.tasks);
+            let status = format!(
+                "Imported {} tasks, skipped {} duplicates.",
+                plan.tasks.len(),
+                plan.duplicates
+            );
+            let tasks = import_tasks(m.tasks.clone(), plan.tasks);
+            let notes = m.notes.clone();
+            let m = Model {
+                transfer: Some(TransferDialog {
+                    table: None,
+                    mapping: vec![],
+                    status: Some(status),
+                    ..dialog
+                }),
+                ..m
+            };
+            record_changes(m, tasks, notes)
+        }
+
+        Msg::ExportTable => {
+            let cmds = match &m.transfer {
+                Some(dialog) if dialog.format == table::Format::Html => {
+                    let tasks: Vec<&Task> = shown_tasks(&m).collect();
+                    let title = format!("cardamom-chai: {}", filter_title(&m));
+                    let page = html::export(&title, &tasks, &m.notes, &m.theme);
+                    vec![Cmd::ExportHtml(dialog.path.clone(), page, tasks.len())]
+                }
+                Some(dialog) => vec![Cmd::ExportTable(
+                    dialog.format,
+                    dialog.path.clone(),
+                    m.tasks.clone(),
+                    m.notes.clone(),
+                )],
+                None => vec![],
+            };
+            (m, cmds)
+        }
+
+        Msg::TransferStatus(status) => {
+            let transfer = m.transfer.map(|dialog| TransferDialog {
+                status: Some(status),
+                ..dialog
+            });
+            (Model { transfer, ..m }, vec![])
+        }
+
+        Msg::CloseTransfer => (
+            Model {
+                transfer: None,
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::OpenSummary => (
+            Model {
+                summary: Some(SummaryDialog::default()),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::SummaryInput(dialog) => (
+            Model {
+                summary: Some(dialog),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::AppendSummary => {
+            let Some(dialog) = &m.summary else {
+                return (m, vec![]);
+            };
+            let report = summary::report(&m.tasks, dialog.period, dialog.back, Local::now());
+            let notes = append_paragraph(&m.notes, &report);
+            record(
+                m,
+                Event::Notes {
+                    notes,
+                    at: Utc::now(),
+                },
+            )
+        }
+
+        Msg::IpcRequest(id, request) => ipc_request(m, id, request),
+
+        Msg::Replay(event) => record(m, event),
+
+        Msg::CloseSummary => (Model { summary: None, ..m }, vec![]),
+
+        Msg::ResolveTaskConflict(id, side) => {
+            let mut conflicts = m.conflicts.clone();
+            let mut tasks = m.tasks.clone();
+            if let Some(i) = conflicts.iter().position(|c| c.task_id == id) {
+                let conflict = conflicts.remove(i);
+                merge::resolve(&mut tasks, &conflict, side);
+            }
+            let notes = m.notes.clone();
+            record_changes(Model { conflicts, ..m }, tasks, notes)
+        }
+
+        Msg::ResolveNotesConflict(side) => match m.notes_conflict {
+            Some(theirs) => {
+                let notes = match side {
+                    merge::Side::Mine => m.notes.clone(),
+                    merge::Side::Theirs => theirs.clone(),
+                    merge::Side::Both => merge::merge_notes(&m.notes, &theirs),
+                };
+                // the change is recorded against the notes on disk
+                let tasks = m.tasks.clone();
+                let m = Model {
+                    notes: theirs,
+                    notes_conflict: None,
+                    ..m
+                };
+                record_changes(m, tasks, notes)
+            }
+            None => (m, vec![]),
+        },
+
+        Msg::SetTheme(theme_name) => {
+            let settings = Settings {
+                theme: theme_name.clone(),
+                ..m.settings
+            };
+            let saved_settings = Settings {
+                theme: theme_name.clone(),
+                ..m.saved_settings
+            };
+            (
+                Model {
+                    settings,
+                    saved_settings: saved_settings.clone(),
+                    ..m
+                },
+                vec![
+                    Cmd::LoadTheme(theme_name),
+                    Cmd::WriteSettings(saved_settings),
+                ],
+            )
+        }
+
+        Msg::ExportTheme => {
+            let theme = m.theme.clone();
+            (
+                m,
+                vec![Cmd::WriteTheme(theme::EXPORTED_THEME.to_string(), theme)],
+            )
+        }
+
+        Msg::LoadedSettings(mut saved_settings, overrides) => {
+            // a hand-edited name could point outside the profiles directory
+            let mut profile_status = None;
+            if !profiles::is_valid_name(&saved_settings.profile) {
+                profile_status = Some(format!(
+                    "'{}' is not a valid profile name, so the {} profile is open.",
+                    saved_settings.profile,
+                    profiles::DEFAULT_PROFILE
+                ));
+                saved_settings.profile = profiles::DEFAULT_PROFILE.to_string();
+            }
+            let settings = saved_settings
+                .clone()
+                .with_overrides(&overrides)
+                .unwrap_or_else(|_| saved_settings.clone());
+            let theme_name = settings.theme.clone();
+            let profile = settings.profile.clone();
+            let mut cmds = vec![
+                Cmd::UseStoragePaths(
+                    settings.todo_txt_path.clone(),
+                    settings.markdown_dir.clone(),
+                ),
+                Cmd::UseStorage(settings.storage),
+                Cmd::UseEncryption(settings.encryption),
+                Cmd::UseGit(settings.git_history, settings.git_remote.clone()),
+            ];
+            if settings.http_api != m.settings.http_api
+                || settings.http_port != m.settings.http_port
+            {
+                cmds.push(Cmd::UseHttpApi(settings.http_api, settings.http_port));
+            }
+            let (m, switch_cmds) = if m.profile.as_ref() != Some(&profile) {
+                switch_profile(m, profile)
+            } else {
+                (m, vec![])
+            };
+            cmds.extend(switch_cmds);
+            cmds.push(Cmd::LoadTheme(theme_name));
+            (
+                Model {
+                    settings,
+                    saved_settings,
+                    profile_status,
+                    ..m
+                },
+                cmds,
+            )
+        }
+
+        Msg::OpenSettings => {
+            let settings_edit = Some(m.settings.clone());
+            (Model { settings_edit, ..m }, vec![])
+        }
+
+        Msg::SettingsInput(settings) => (
+            Model {
+                settings_edit: Some(settings),
+                ..m
+            },
+            vec![],
+        ),
+
+        Msg::SaveSettings => match m.settings_edit.clone() {
+            Some(settings) if settings.validate(&m.themes).is_empty() => {
+                let theme_changed = settings.theme != m.settings.theme;
+                let mut cmds = vec![];
+                if settings.git_history != m.settings.git_history
+                    || settings.git_remote != m.settings.git_remote
+                {
+                    cmds.push(Cmd::UseGit(
+                        settings.git_history,
+                        settings.git_remote.clone(),
+                    ));
+                }
+                if settings.http_api != m.settings.http_api
+                    || settings.http_port != m.settings.http_port
+                {
+                    cmds.push(Cmd::UseHttpApi(settings.http_api, settings.http_port));
+                }
+                // files are decrypted before a migration reads them and
+                // encrypted after one wrote them
+                let encryption_changed = settings.encryption != m.settings.encryption;
+                if encryption_changed && !settings.encryption {
+                    cmds.push(Cmd::UseEncryption(false));
+                    cmds.push(Cmd::WriteTasks(m.tasks.clone()));
+                    cmds.push(Cmd::WriteNotes(m.notes.clone()));
+                }
+                let todo_txt_moved = settings.todo_txt_path != m.settings.todo_txt_path;
+                let markdown_moved = settings.markdown_dir != m.settings.markdown_dir;
+                if todo_txt_moved || markdown_moved {
+                    cmds.push(Cmd::UseStoragePaths(
+                        settings.todo_txt_path.clone(),
+                        settings.markdown_dir.clone(),
+                    ));
+                }
+                if settings.storage != m.settings.storage
+                    || (settings.storage == Backend::TodoTxt && todo_txt_moved)
+                    || (settings.storage == Backend::Markdown && markdown_moved)
+                {
+                    cmds.push(Cmd::MigrateStorage(
+                        settings.storage,
+                        m.tasks.clone(),
+                        m.notes.clone(),
+                    ));
+                }
+                let (m, mut switch_cmds) = if m.profile.as_ref() != Some(&settings.profile) {
+                    switch_profile(m, settings.profile.clone())
+                } else if encryption_changed && settings.encryption {
+                    lock(m)
+                } else {
+                    (m, vec![])
+                };
+                if encryption_changed && settings.encryption {
+                    // after the flushing writes, before locking or switching
+                    switch_cmds.insert(switch_cmds.len() - 1, Cmd::UseEncryption(true));
+                }
+                cmds.extend(switch_cmds);
+                let saved_settings = m.saved_settings.with_changes(&m.settings, &settings);
+                cmds.push(Cmd::WriteSettings(saved_settings.clone()));
+                if theme_changed {
+                    cmds.push(Cmd::LoadTheme(settings.theme.clone()));
+                }
+                (
+                    Model {
+                        settings,
+                        saved_settings,
+                        settings_edit: None,
+                        ..m
+                    },
+                    cmds,
+                )
+            }
+            _ => (m, vec![]),
+        },
+
+        Msg::CloseSettings => (
+            Model {
+                settings_edit: None,
+                ..m
+            },
+            vec![],
+        ),
+    }
+}
+
+/// Handles a request from another program like the messages the UI
+/// sends for the same change, and answers it.
+fn ipc_request(m: Model, id: Uuid, request: ipc::Request) -> (Model, Vec<Cmd>) {
+    if m.locked.is_some() {
+        let reply = Cmd::IpcReply(id, ipc::Response::error("the app is locked"));
+        return (m, vec![reply]);
+    }
+    if !m.loaded {
+        let reply = Cmd::IpcReply(id, ipc::Response::error("the app is still loading"));
+        return (m, vec![reply]);
+    }
+    let done = |task: Option<&Task>| ipc::Response {
+        ok: true,
+        task_id: task.map(|task| task.task_id),
+        tasks: task.map(|task| vec![task.clone()]),
+        ..ipc::Response::default()
+    };
+    let changed = |m: &Model, task_id| done(m.tasks.iter().find(|t| t.task_id == task_id));
+    let found = |m: &Model, task_id: &str| find_task(&m.tasks, task_id).map(|task| task.task_id);
+
+    let (m, mut cmds, response) = match request {
+        ipc::Request::Add { text } => {
+            let task = Task::quick_add(&text);
+            if task.task_text.is_empty() {
+                (m, vec![], ipc::Response::error("the task has no text"))
+            } else {
+                let task_id = task.task_id;
+                let (m, cmds) = record(m, Event::Add { task });
+                let response = changed(&m, task_id);
+                (m, cmds, response)
+            }
+        }
+        ipc::Request::Complete { task_id } => match found(&m, &task_id) {
+            Ok(task_id) => {
+                let event = Event::CheckBox {
+                    task_id,
+                    done: true,
+                    at: Utc::now(),
+                };
+                let (m, cmds) = record(m, event);
+                let response = changed(&m, task_id);
+                (m, cmds, response)
+            }
+            Err(e) => (m, vec![], ipc::Response::not_found(e)),
+        },
+        ipc::Request::Update {
+            task_id,
+            task_text,
+            done,
+            state,
+        } => match found(&m, &task_id) {
+            Ok(task_id) => {
+                let at = Utc::now();
+                let mut events = vec![];
+                if let Some(text) = task_text {
+                    events.push(Event::EditDone { task_id, text, at });
+                }
+                if let Some(done) = done {
+                    events.push(Event::CheckBox { task_id, done, at });
+                }
+                if let Some(state) = state {
+                    events.push(Event::CycleTaskState { task_id, state, at });
+                }
+                let mut m = m;
+                let mut cmds = vec![];
+                for event in events {
+                    let (next, more) = record(m, event);
+                    m = next;
+                    cmds.extend(more);
+                }
+                let response = changed(&m, task_id);
+                (m, cmds, response)
+            }
+            Err(e) => (m, vec![], ipc::Response::not_found(e)),
+        },
+        ipc::Request::Delete { task_id } => match found(&m, &task_id) {
+            Ok(task_id) => {
+                let event = Event::Delete {
+                    task_id,
+                    at: Utc::now(),
+                };
+                let (m, cmds) = record(m, event);
+                let response = ipc::Response {
+                    task_id: Some(task_id),
+                    ..done(None)
+                };
+                (m, cmds, response)
+            }
+            Err(e) => (m, vec![], ipc::Response::not_found(e)),
+        },
+        ipc::Request::Query { filter, search } => {
+            let tasks = m
+                .tasks
+                .iter()
+                .rev()
+                .filter(|task| filter.shows(task, &search))
+                .cloned()
+                .collect();
+            let response = ipc::Response {
+                tasks: Some(tasks),
+                ..done(None)
+            };
+            (m, vec![], response)
+        }
+        ipc::Request::AppendNote { text } => {
+            let notes = append_paragraph(&m.notes, text.trim());
+            let (m, cmds) = record(
+                m,
+                Event::Notes {
+                    notes,
+                    at: Utc::now(),
+                },
+            );
+            (m, cmds, done(None))
+        }
+        ipc::Request::GetNotes => {
+            let response = ipc::Response {
+                notes: Some(m.notes.clone()),
+                ..done(None)
+            };
+            (m, vec![], response)
+        }
+        ipc::Request::SetNotes { notes } => {
+            let (m, cmds) = record(
+                m,
+                Event::Notes {
+                    notes,
+                    at: Utc::now(),
+                },
+            );
+            let response = ipc::Response {
+                notes: Some(m.notes.clone()),
+                ..done(None)
+            };
+            (m, cmds, response)
+        }
+    };
+    cmds.push(Cmd::IpcReply(id, response));
+    (m, cmds)
+}
+
+/// Flushes tasks and notes, then hides them behind the unlock screen until
+/// the passphrase is entered again.
+fn lock(m: Model) -> (Model, Vec<Cmd>) {
+    let cmds = vec![
+        Cmd::WriteTasks(m.tasks.clone()),
+        Cmd::WriteNotes(m.notes.clone()),
+        Cmd::Lock,
+    ];
+    (
+        Model {
+            tasks: vec![],
+            notes: "".to_string(),
+            edit_tasks: vec![],
+            notes_state: NotesState::Display,
+            settings_edit: None,
+            history: None,
+            matches: None,
+            loaded: false,
+            ..m
+        },
+        cmds,
+    )
+}
+
+/// Flushes the current profile's tasks and notes, then points storage at
+/// `profile`. The model is emptied until `Msg::ProfileSwitched` reloads it.
+fn switch_profile(m: Model, profile: String) -> (Model, Vec<Cmd>) {
+    let mut cmds = vec![];
+    if m.profile.is_some() {
+        cmds.push(Cmd::WriteTasks(m.tasks.clone()));
+        cmds.push(Cmd::WriteNotes(m.notes.clone()));
+    }
+    cmds.push(Cmd::SwitchProfile(profile));
+
+    (
+        Model {
+            tasks: vec![],
+            notes: "".to_string(),
+            edit_tasks: vec![],
+            notes_state: NotesState::Display,
+            loaded: false,
+            ..m
+        },
+        cmds,
+    )
+}
+
+/// The tasks the list shows, newest first.
+fn shown_tasks(m: &Model) -> impl Iterator<Item = &Task> {
+    m.tasks.iter().rev().filter(|t| match &m.matches {
+        Some(matches) => matches.contains(&t.task_id),
+        None => m
+            .filter
+            .shows(t, m.add_task_text_box.trim_start_matches('/')),
+    })
+}
+
+fn filter_title(m: &Model) -> String {
+    match m.filter {
+        Filter::All => "all tasks".to_string(),
+        Filter::Active => "active tasks".to_string(),
+        Filter::Pending => "pending tasks".to_string(),
+        Filter::Uncertain => "uncertain tasks".to_string(),
+        Filter::Search => format!(
+            "tasks matching \"{}\"",
+            m.add_task_text_box.trim_start_matches('/')
+        ),
+        Filter::Done => "done tasks".to_string(),
+    }
+}
+
+fn view(ctx: &egui::Context, m: &Model, tx: &mut Vec<Msg>) {
+    if let Some(locked) = &m.locked {
+        unlock_screen(ctx, locked, tx);
+        return;
+    }
+
+    if m.settings.encryption
+        && m.settings.auto_lock_minutes > 0
+        && let Some(last_activity) = m.last_activity
+    {
+        let idle = last_activity.elapsed();
+        if idle > Duration::from_secs(1) && ctx.input(|i| !i.events.is_empty()) {
+            tx.push(Msg::Activity);
+        }
+        let timeout = Duration::from_secs(u64::from(m.settings.auto_lock_minutes) * 60);
+        match timeout.checked_sub(idle) {
+            Some(remaining) if !remaining.is_zero() => ctx.request_repaint_after(remaining),
+            _ => tx.push(Msg::LockNow),
+        }
+    }
+
+    if let Some(settings) = &m.settings_edit {
+        settings_window(ctx, m, settings, tx);
+    }
+
+    if let lock::Status::HeldBy(pid) = m.instance {
+        egui::TopBottomPanel::top("read_only_banner").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let owner = match pid {
+                    Some(pid) => format!("another cardamom-chai (pid {pid})"),
+                    None => "another cardamom-chai".to_string(),
+                };
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("Read-only: {owner} is using this data directory. Changes here will not be saved."),
+                );
+                if ui.button("retry").clicked() {
+                    tx.push(Msg::RetryLock);
+                }
+            });
+        });
+    }
+
+    if let Some(history) = &m.history {
+        history_window(ctx, history, tx);
+    }
+
+    if let Some(dialog) = &m.markdown {
+        markdown_window(ctx, m, dialog, tx);
+    }
+
+    if let Some(dialog) = &m.transfer {
+        transfer_window(ctx, m, dialog, tx);
+    }
+
+    if let Some(dialog) = &m.summary {
+        summary_window(ctx, m, dialog, tx);
+    }
+
+    if !m.conflicts.is_empty() || m.notes_conflict.is_some() {
+        conflicts_window(ctx, m, tx);
+    }
+
+    let mut new_profile_has_focus = false;
+    let left_width = m.settings.left_panel_width;
+    egui::SidePanel::left("left_panel")
+        .resizable(true)
+        .default_width(left_width)
+        .width_range(80.0..=left_width.max(350.0))
+        .show(ctx, |ui| {
+            ui.add_space(10.0);
+            ui.label(RichText::new("Theme").strong());
+            egui::ComboBox::from_id_salt("theme_select")
+                .selected_text(&m.settings.theme)
+                .show_ui(ui, |ui| {
+                    for name in &m.themes {
+                        if ui
+                            .selectable_label(*name == m.settings.theme, name)
+                            .clicked()
+                        {
+                            tx.push(Msg::SetTheme(name.clone()));
+                        }
+                    }
+                });
+            ui.horizontal(|ui| {
+                if ui
+                    .button("export")
+                    .on_hover_text("Save the current theme as current-theme.json")
+                    .clicked()
+                {
+                    tx.push(Msg::ExportTheme);
+                }
+                if ui.button("⚙ settings").clicked() {
+                    tx.push(Msg::OpenSettings);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("☑ markdown").clicked() {
+                    tx.push(Msg::OpenMarkdown);
+                }
+                if ui.button("⇄ import/export").clicked() {
+                    tx.push(Msg::OpenTransfer);
+                }
+                if ui.button("📋 summary").clicked() {
+                    tx.push(Msg::OpenSummary);
+                }
+                if m.settings.git_history && ui.button("🕘 history").clicked() {
+                    tx.push(Msg::OpenHistory);
+                }
+            });
+
+            ui.add_space(10.0);
+            ui.label(RichText::new("Profile").strong());
+            let current = m.profile.as_deref().unwrap_or(&m.settings.profile);
+            egui::ComboBox::from_id_salt("profile_select")
+                .selected_text(current)
+                .show_ui(ui, |ui| {
+                    for name in &m.profiles {
+                        if ui.selectable_label(name == current, name).clicked() {
+                            tx.push(Msg::SetProfile(name.clone()));
+                        }
+                    }
+                });
+            if let Some(status) = &m.profile_status {
+                ui.label(status);
+            }
+            let mut new_profile_text_box = m.new_profile_text_box.clone();
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut new_profile_text_box)
+                    .hint_text("New profile...")
+                    .desired_width(120.0),
+            );
+            if response.changed() {
+                tx.push(Msg::NewProfileInput(new_profile_text_box.clone()));
+            }
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                tx.push(Msg::SetProfile(new_profile_text_box.trim().to_string()));
+            }
+            new_profile_has_focus = response.has_focus();
+        });
+
+    let right_width = m.settings.right_panel_width;
+    egui::SidePanel::right("right_panel")
+        .resizable(true)
+        .default_width(right_width)
+        .width_range(80.0..=right_width.max(350.0))
+        .show(ctx, |ui| {
+            ui.add_space(10.0);
+            ui.take_available_space();
+            let response = ui.label(RichText::new("Notes").strong().size(17.0));
+            if response.middle_clicked() {
+                match m.notes_state {
+                    NotesState::Display => tx.push(Msg::EditNote),
+                    NotesState::Edit => tx.push(Msg::EditNoteDone),
+                }
+            }
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .show(ui, |ui| match m.notes_state {
+                    NotesState::Display => {
+                        let mut cache = egui_commonmark::CommonMarkCache::default();
+                        egui_commonmark::CommonMarkViewer::new().show(ui, &mut cache, &m.notes);
+                    }
+
+                    NotesState::Edit => {
+                        let mut notes_text_box = m.notes.clone();
+                        let response = ui.add(egui::TextEdit::multiline(&mut notes_text_box));
+
+                        if response.changed() {
+                            tx.push(Msg::EditNoteInput(notes_text_box));
+                        }
+
+                        if response.has_focus() {
+                            ui.input(|i| {
+                                if i.key_pressed(egui::Key::Enter) && i.modifiers.ctrl {
+                                    tx.push(Msg::EditNoteDone);
+                                }
+                            });
+                        }
+
+                        if ui.button("save").clicked() {
+                            tx.push(Msg::EditNoteDone);
+                        }
+                    }
+                });
+        });
+
+    egui::TopBottomPanel::bottom("bottom_panel")
+        .resizable(false)
+        .min_height(0.0)
+        .show(ctx, |ui| {
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                let mut filter = m.filter;
+                let mut changed = false;
+
+                changed |= ui
+                    .selectable_value(&mut filter, Filter::All, "All")
+                    .changed();
+                changed |= ui
+                    .selectable_value(&mut filter, Filter::Active, "Active")
+                    .changed();
                 changed |= ui
                     .selectable_value(&mut filter, Filter::Pending, "Pending")
                     .changed();
@@ -335,289 +2022,1901 @@ fn view(ctx: &egui::Context, m: &Model, tx: &mut Vec<Msg>) {
                     .selectable_value(&mut filter, Filter::Done, "Done")
                     .changed();
 
-                if changed {
-                    tx.push(Msg::SetFilter(filter));
+                if changed {
+                    tx.push(Msg::SetFilter(filter));
+                }
+            });
+        });
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        let mut add_task_text_box_has_focus = false;
+        let mut task_edit_box_has_focus = false;
+        let text_edit_id = ui.make_persistent_id("add_task_text_box");
+
+        ui.vertical_centered(|ui| {
+            ui.heading("cardamom chai");
+            ui.add_space(12.0);
+
+            ui.vertical_centered(|ui| {
+                let mut add_task_text_box = m.add_task_text_box.clone();
+
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut add_task_text_box)
+                        .hint_text("Add a task... '/' to search...")
+                        .id(text_edit_id),
+                );
+
+                if response.changed() {
+                    match m.filter {
+                        Filter::Search => {
+                            if add_task_text_box.is_empty() {
+                                tx.push(Msg::SetFilter(Filter::All));
+                            }
+                        }
+                        _ => {
+                            if add_task_text_box.starts_with('/') {
+                                tx.push(Msg::SetFilter(Filter::Search));
+                            }
+                        }
+                    }
+
+                    tx.push(Msg::TextInput(add_task_text_box));
+                }
+
+                if response.lost_focus()
+                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                    && !matches!(m.filter, Filter::Search)
+                {
+                    tx.push(Msg::Add);
+                    ui.memory_mut(|mem| mem.request_focus(text_edit_id));
+                }
+
+                add_task_text_box_has_focus = response.has_focus();
+            });
+
+            ui.add_space(12.0);
+
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .show(ui, |ui| {
+                    for task in shown_tasks(m) {
+                        ui.horizontal_wrapped(|ui| {
+                            let mut checked = task.done;
+
+                            let asterisk = task.task_text.ends_with('*');
+                            let trimmed_text = task.task_text.trim_end_matches('*');
+                            let text = if checked {
+                                RichText::new(trimmed_text).strikethrough().weak()
+                            } else {
+                                let colors = &m.theme.tasks;
+                                match task.state {
+                                    TaskState::Normal => match colors.normal {
+                                        Some(color) => RichText::new(trimmed_text).color(color.0),
+                                        None => RichText::new(trimmed_text),
+                                    },
+                                    TaskState::Chosen => RichText::new(trimmed_text)
+                                        .color(colors.chosen.0)
+                                        .underline(),
+                                    TaskState::Uncertain => {
+                                        RichText::new(format!("{}?", trimmed_text))
+                                            .color(colors.uncertain.0)
+                                    }
+                                }
+                            };
+
+                            if m.edit_tasks.contains(&task.task_id) {
+                                let mut edit_task_text_box = task.task_text.clone();
+                                let _ = ui.checkbox(&mut checked, "");
+                                let response = ui.add(
+                                    egui::TextEdit::singleline(&mut edit_task_text_box)
+                                        .desired_width(700.0),
+                                );
+
+                                if response.changed() {
+                                    tx.push(Msg::EditInput(task.task_id, edit_task_text_box));
+                                }
+
+                                if response.lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                                {
+                                    tx.push(Msg::EditDone(task.task_id));
+                                }
+                                task_edit_box_has_focus |= response.has_focus();
+                            } else {
+                                let check_response = ui.checkbox(&mut checked, text);
+
+                                if check_response.changed() {
+                                    tx.push(Msg::CheckBox(task.task_id, checked));
+                                }
+
+                                if check_response.middle_clicked() {
+                                    tx.push(Msg::Edit(task.task_id));
+                                }
+
+                                if check_response.secondary_clicked() {
+                                    tx.push(Msg::CycleTaskState(task.task_id));
+                                }
+
+                                if (checked || matches!(task.state, TaskState::Uncertain))
+                                    && ui.button("🗑").clicked()
+                                {
+                                    tx.push(Msg::Delete(task.task_id));
+                                }
+
+                                if checked && !asterisk && ui.button("🔁").clicked() {
+                                    tx.push(Msg::Reschedule(task.task_text.clone()));
+                                }
+
+                                if checked && asterisk && ui.button("⟲").clicked() {
+                                    tx.push(Msg::RescheduleActive(task.task_text.clone()));
+                                    tx.push(Msg::CycleTaskState(task.task_id));
+                                }
+                            }
+                        });
+                    }
+                });
+        });
+        //hotkeys
+        let hotkeys = &m.settings.hotkeys;
+        if !add_task_text_box_has_focus
+            && !task_edit_box_has_focus
+            && !matches!(m.notes_state, NotesState::Edit)
+            && m.settings_edit.is_none()
+            && !new_profile_has_focus
+        {
+            if settings::pressed(ui, &hotkeys.focus_add) {
+                ui.memory_mut(|mem| mem.request_focus(text_edit_id));
+            }
+
+            if settings::pressed(ui, &hotkeys.search) {
+                if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), text_edit_id) {
+                    let ccursor = egui::text::CCursor::new(1);
+                    state
+                        .cursor
+                        .set_char_range(Some(egui::text::CCursorRange::one(ccursor)));
+                    state.store(ui.ctx(), text_edit_id);
+                    ui.ctx().memory_mut(|mem| mem.request_focus(text_edit_id)); // give focus back to the [`TextEdit`].
+                }
+                tx.push(Msg::TextInput("/".to_string()));
+                tx.push(Msg::SetFilter(Filter::Search));
+            }
+
+            if settings::pressed(ui, &hotkeys.filter_all) {
+                tx.push(Msg::SetFilter(Filter::All));
+            }
+
+            if settings::pressed(ui, &hotkeys.filter_active) {
+                tx.push(Msg::SetFilter(Filter::Active));
+            }
+
+            if settings::pressed(ui, &hotkeys.filter_pending) {
+                tx.push(Msg::SetFilter(Filter::Pending));
+            }
+
+            if settings::pressed(ui, &hotkeys.filter_uncertain) {
+                tx.push(Msg::SetFilter(Filter::Uncertain));
+            }
+
+            if settings::pressed(ui, &hotkeys.filter_done) {
+                tx.push(Msg::SetFilter(Filter::Done));
+            }
+        }
+    });
+}
+
+fn unlock_screen(ctx: &egui::Context, locked: &Locked, tx: &mut Vec<Msg>) {
+    let mut form = locked.form.clone();
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.vertical_centered(|ui| {
+            ui.add_space(60.0);
+            ui.heading("cardamom chai");
+            ui.add_space(12.0);
+            ui.label(if locked.existing {
+                "Enter your passphrase to unlock your tasks and notes."
+            } else {
+                "Choose a passphrase to encrypt your tasks and notes."
+            });
+            ui.add_space(6.0);
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut form.passphrase)
+                    .password(true)
+                    .hint_text("passphrase"),
+            );
+            let mut submitted =
+                response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if !locked.existing {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut form.confirm)
+                        .password(true)
+                        .hint_text("confirm passphrase"),
+                );
+                submitted |= response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            }
+
+            if let Some(error) = &form.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+            ui.add_space(6.0);
+            if form.busy {
+                ui.spinner();
+            } else if ui.button("unlock").clicked() || submitted {
+                tx.push(Msg::Unlock);
+            }
+        });
+    });
+
+    if form.passphrase != locked.form.passphrase || form.confirm != locked.form.confirm {
+        tx.push(Msg::UnlockInput(form));
+    }
+}
+
+fn settings_window(ctx: &egui::Context, m: &Model, settings: &Settings, tx: &mut Vec<Msg>) {
+    let themes = &m.themes;
+    let mut edit = settings.clone();
+    let mut open = true;
+
+    egui::Window::new("Settings")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("settings_grid")
+                .num_columns(2)
+                .spacing([12.0, 6.0])
+                .show(ui, |ui| {
+                    ui.label("profile");
+                    ui.add(egui::TextEdit::singleline(&mut edit.profile).desired_width(120.0));
+                    ui.end_row();
+
+                    ui.label("storage");
+                    egui::ComboBox::from_id_salt("settings_storage")
+                        .selected_text(edit.storage.name())
+                        .show_ui(ui, |ui| {
+                            for storage in Backend::ALL {
+                                ui.selectable_value(&mut edit.storage, storage, storage.name());
+                            }
+                        });
+                    ui.end_row();
+
+                    if edit.storage == Backend::TodoTxt {
+                        ui.label("todo.txt path");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut edit.todo_txt_path)
+                                .hint_text("profile directory")
+                                .desired_width(200.0),
+                        );
+                        ui.end_row();
+                    }
+
+                    if edit.storage == Backend::Markdown {
+                        ui.label("markdown folder");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut edit.markdown_dir)
+                                .hint_text("profile directory")
+                                .desired_width(200.0),
+                        );
+                        ui.end_row();
+                    }
+
+                    ui.label("encryption");
+                    ui.checkbox(&mut edit.encryption, "passphrase protected");
+                    ui.end_row();
+
+                    if edit.encryption && !m.settings.encryption && m.settings.git_history {
+                        ui.label("");
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            "versions already in the git history stay unencrypted",
+                        );
+                        ui.end_row();
+                    }
+
+                    if edit.encryption {
+                        ui.label("auto lock minutes");
+                        ui.add(egui::DragValue::new(&mut edit.auto_lock_minutes).speed(1.0));
+                        ui.end_row();
+                    }
+
+                    ui.label("http api");
+                    ui.checkbox(&mut edit.http_api, "serve on localhost");
+                    ui.end_row();
+
+                    if edit.http_api {
+                        ui.label("http port");
+                        ui.add(egui::DragValue::new(&mut edit.http_port).speed(1.0));
+                        ui.end_row();
+                    }
+
+                    ui.label("theme");
+                    egui::ComboBox::from_id_salt("settings_theme")
+                        .selected_text(&edit.theme)
+                        .show_ui(ui, |ui| {
+                            for name in themes {
+                                ui.selectable_value(&mut edit.theme, name.clone(), name);
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("left panel width");
+                    ui.add(egui::DragValue::new(&mut edit.left_panel_width).speed(1.0));
+                    ui.end_row();
+
+                    ui.label("right panel width");
+                    ui.add(egui::DragValue::new(&mut edit.right_panel_width).speed(1.0));
+                    ui.end_row();
+
+                    for (name, key) in edit.hotkeys.bindings_mut() {
+                        ui.label(name.replace('_', " "));
+                        ui.add(egui::TextEdit::singleline(key).desired_width(80.0));
+                        ui.end_row();
+                    }
+                });
+
+            if m.settings.encryption {
+                ui.add_space(6.0);
+                passphrase_change(ui, &m.passphrase_change, tx);
+            }
+
+            if m.settings.storage != Backend::Json {
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    if ui
+                        .button(format!("import {TASK_DATABASE_FILENAME}"))
+                        .clicked()
+                    {
+                        tx.push(Msg::ImportJsonDatabase);
+                    }
+                    if ui
+                        .button(format!("export {TASK_DATABASE_FILENAME}"))
+                        .clicked()
+                    {
+                        tx.push(Msg::ExportJsonDatabase);
+                    }
+                });
+            }
+
+            let errors = edit.validate(themes);
+            for error in &errors {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            ui.add_space(6.0);
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(errors.is_empty(), egui::Button::new("save"))
+                    .clicked()
+                {
+                    tx.push(Msg::SaveSettings);
+                }
+                if ui.button("cancel").clicked() {
+                    tx.push(Msg::CloseSettings);
+                }
+            });
+        });
+
+    if edit != *settings {
+        tx.push(Msg::SettingsInput(edit));
+    }
+    if !open {
+        tx.push(Msg::CloseSettings);
+    }
+}
+
+fn passphrase_change(ui: &mut egui::Ui, form: &PassphraseForm, tx: &mut Vec<Msg>) {
+    let mut edit = form.clone();
+
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut edit.passphrase)
+                .password(true)
+                .hint_text("new passphrase")
+                .desired_width(120.0),
+        );
+        ui.add(
+            egui::TextEdit::singleline(&mut edit.confirm)
+                .password(true)
+                .hint_text("confirm")
+                .desired_width(120.0),
+        );
+        if form.busy {
+            ui.spinner();
+        } else if ui.button("change passphrase").clicked() {
+            tx.push(Msg::ChangePassphrase);
+        }
+    });
+    if let Some(error) = &form.error {
+        ui.colored_label(ui.visuals().error_fg_color, error);
+    }
+    if ui.button("lock now").clicked() {
+        tx.push(Msg::LockNow);
+    }
+
+    if edit.passphrase != form.passphrase || edit.confirm != form.confirm {
+        tx.push(Msg::PassphraseChangeInput(edit));
+    }
+}
+
+fn task_summary(task: Option<&Task>) -> String {
+    match task {
+        None => "(deleted)".to_string(),
+        Some(task) => {
+            let check = if task.done { "[x]" } else { "[ ]" };
+            let marker = match task.state {
+                TaskState::Normal => "",
+                TaskState::Chosen => " !",
+                TaskState::Uncertain => " ?",
+            };
+            format!("{check} {}{marker}", task.task_text)
+        }
+    }
+}
+
+fn conflicts_window(ctx: &egui::Context, m: &Model, tx: &mut Vec<Msg>) {
+    egui::Window::new("Changed on disk")
+        .collapsible(false)
+        .default_width(480.0)
+        .show(ctx, |ui| {
+            ui.label("These were edited both here and in the database file.");
+            ui.add_space(6.0);
+
+            for conflict in &m.conflicts {
+                ui.group(|ui| {
+                    ui.label(format!("mine:   {}", task_summary(conflict.mine.as_ref())));
+                    ui.label(format!(
+                        "theirs: {}",
+                        task_summary(conflict.theirs.as_ref())
+                    ));
+                    ui.horizontal(|ui| {
+                        if ui.button("keep mine").clicked() {
+                            tx.push(Msg::ResolveTaskConflict(
+                                conflict.task_id,
+                                merge::Side::Mine,
+                            ));
+                        }
+                        if ui.button("keep theirs").clicked() {
+                            tx.push(Msg::ResolveTaskConflict(
+                                conflict.task_id,
+                                merge::Side::Theirs,
+                            ));
+                        }
+                        if conflict.mine.is_some()
+                            && conflict.theirs.is_some()
+                            && ui.button("keep both").clicked()
+                        {
+                            tx.push(Msg::ResolveTaskConflict(
+                                conflict.task_id,
+                                merge::Side::Both,
+                            ));
+                        }
+                    });
+                });
+            }
+
+            if let Some(theirs) = &m.notes_conflict {
+                ui.group(|ui| {
+                    ui.label(RichText::new("Notes").strong());
+                    ui.columns(2, |columns| {
+                        columns[0].label("mine");
+                        columns[1].label("theirs");
+                        egui::ScrollArea::vertical()
+                            .id_salt("notes_mine")
+                            .max_height(200.0)
+                            .show(&mut columns[0], |ui| ui.monospace(&m.notes));
+                        egui::ScrollArea::vertical()
+                            .id_salt("notes_theirs")
+                            .max_height(200.0)
+                            .show(&mut columns[1], |ui| ui.monospace(theirs));
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("keep mine").clicked() {
+                            tx.push(Msg::ResolveNotesConflict(merge::Side::Mine));
+                        }
+                        if ui.button("keep theirs").clicked() {
+                            tx.push(Msg::ResolveNotesConflict(merge::Side::Theirs));
+                        }
+                        if ui.button("keep both").clicked() {
+                            tx.push(Msg::ResolveNotesConflict(merge::Side::Both));
+                        }
+                    });
+                });
+            }
+        });
+}
+
+fn history_window(ctx: &egui::Context, history: &History, tx: &mut Vec<Msg>) {
+    let mut open = true;
+    egui::Window::new("History")
+        .open(&mut open)
+        .default_size([640.0, 420.0])
+        .show(ctx, |ui| {
+            ui.columns(2, |columns| {
+                egui::ScrollArea::vertical()
+                    .id_salt("history_commits")
+                    .show(&mut columns[0], |ui| {
+                        if history.commits.is_empty() {
+                            ui.weak("No commits yet.");
+                        }
+                        for commit in &history.commits {
+                            let selected = history.selected.as_ref() == Some(&commit.hash);
+                            let label = format!("{}\n{}", commit.message, commit.date);
+                            if ui.selectable_label(selected, label).clicked() {
+                                tx.push(Msg::SelectCommit(commit.hash.clone()));
+                            }
+                        }
+                    });
+
+                let ui = &mut columns[1];
+                if history.selected.is_none() {
+                    ui.weak("Select a commit to preview it.");
+                    return;
+                }
+                egui::ScrollArea::vertical()
+                    .id_salt("history_preview")
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for task in history.tasks.iter().rev() {
+                            ui.label(task_summary(Some(task)));
+                        }
+                        ui.separator();
+                        let mut cache = egui_commonmark::CommonMarkCache::default();
+                        egui_commonmark::CommonMarkViewer::new().show(
+                            ui,
+                            &mut cache,
+                            &history.notes,
+                        );
+                    });
+                if ui.button("restore this version").clicked() {
+                    tx.push(Msg::RestoreCommit);
+                }
+            });
+        });
+
+    if !open {
+        tx.push(Msg::CloseHistory);
+    }
+}
+
+fn markdown_window(ctx: &egui::Context, m: &Model, dialog: &MarkdownDialog, tx: &mut Vec<Msg>) {
+    let mut open = true;
+    egui::Window::new("Markdown checklist")
+        .open(&mut open)
+        .default_width(420.0)
+        .show(ctx, |ui| {
+            let mut edit = dialog.clone();
+
+            ui.horizontal(|ui| {
+                ui.label("group by");
+                for grouping in markdown::Grouping::ALL {
+                    ui.radio_value(&mut edit.grouping, grouping, grouping.name());
+                }
+            });
+            if ui.button("copy checklist").clicked() {
+                ctx.copy_text(markdown::export(&m.tasks, edit.grouping));
+            }
+            // with markdown storage the file is the task list itself
+            if m.settings.storage != Backend::Markdown {
+                ui.horizontal(|ui| {
+                    ui.label("file");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut edit.path)
+                            .hint_text("path, relative to the profile directory")
+                            .desired_width(200.0),
+                    );
+                    if ui.button("import").clicked() {
+                        tx.push(Msg::ImportMarkdownFile);
+                    }
+                    if ui.button("export").clicked() {
+                        tx.push(Msg::ExportMarkdownFile);
+                    }
+                });
+                if let Some(status) = &dialog.status {
+                    ui.label(status);
+                }
+            }
+
+            ui.separator();
+            ui.label("Paste a checklist to add its items as new tasks:");
+            ui.add(
+                egui::TextEdit::multiline(&mut edit.paste)
+                    .hint_text("- [ ] something to do")
+                    .desired_rows(6)
+                    .desired_width(f32::INFINITY),
+            );
+            if ui
+                .add_enabled(!edit.paste.trim().is_empty(), egui::Button::new("import"))
+                .clicked()
+            {
+                tx.push(Msg::ImportMarkdown);
+            }
+
+            if edit.grouping != dialog.grouping
+                || edit.paste != dialog.paste
+                || edit.path != dialog.path
+            {
+                tx.push(Msg::MarkdownInput(edit));
+            }
+        });
+
+    if !open {
+        tx.push(Msg::CloseMarkdown);
+    }
+}
+
+fn summary_window(ctx: &egui::Context, m: &Model, dialog: &SummaryDialog, tx: &mut Vec<Msg>) {
+    let mut open = true;
+    egui::Window::new("Summary")
+        .open(&mut open)
+        .default_width(420.0)
+        .show(ctx, |ui| {
+            let mut edit = dialog.clone();
+            let now = Local::now();
+
+            ui.horizontal(|ui| {
+                for period in summary::Period::ALL {
+                    if ui
+                        .radio_value(&mut edit.period, period, period.name())
+                        .changed()
+                    {
+                        edit.back = 0;
+                    }
+                }
+                ui.separator();
+                if ui.button("◀").clicked() {
+                    edit.back += 1;
+                }
+                ui.label(summary::title(edit.period, edit.back, now));
+                if ui
+                    .add_enabled(edit.back > 0, egui::Button::new("▶"))
+                    .clicked()
+                {
+                    edit.back -= 1;
+                }
+            });
+
+            let report = summary::report(&m.tasks, edit.period, edit.back, now);
+            ui.horizontal(|ui| {
+                if ui.button("copy").clicked() {
+                    ctx.copy_text(report.clone());
+                }
+                if ui.button("append to notes").clicked() {
+                    tx.push(Msg::AppendSummary);
+                }
+            });
+
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .max_height(400.0)
+                .show(ui, |ui| {
+                    let mut cache = egui_commonmark::CommonMarkCache::default();
+                    egui_commonmark::CommonMarkViewer::new().show(ui, &mut cache, &report);
+                });
+
+            if edit != *dialog {
+                tx.push(Msg::SummaryInput(edit));
+            }
+        });
+
+    if !open {
+        tx.push(Msg::CloseSummary);
+    }
+}
+
+/// Rows shown before importing a table.
+const PREVIEW_ROWS: usize = 5;
+
+fn transfer_window(ctx: &egui::Context, m: &Model, dialog: &TransferDialog, tx: &mut Vec<Msg>) {
+    let mut open = true;
+    egui::Window::new("Import / export")
+        .open(&mut open)
+        .default_width(520.0)
+        .show(ctx, |ui| {
+            let mut edit = dialog.clone();
+
+            ui.horizontal(|ui| {
+                ui.label("format");
+                // with todo.txt storage the file is the task list itself
+                let formats = table::Format::ALL.into_iter().filter(|format| {
+                    !(*format == table::Format::TodoTxt && m.settings.storage == Backend::TodoTxt)
+                });
+                for format in formats {
+                    if ui
+                        .radio_value(&mut edit.format, format, format.name())
+                        .changed()
+                        && edit.path == dialog.format.default_filename()
+                    {
+                        edit.path = format.default_filename().to_string();
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("file");
+                ui.add(
+                    egui::TextEdit::singleline(&mut edit.path)
+                        .hint_text("path, relative to the profile directory")
+                        .desired_width(260.0),
+                );
+                let read = if edit.format.keeps_ids() {
+                    "import"
+                } else {
+                    "read"
+                };
+                if ui
+                    .add_enabled(edit.format.importable(), egui::Button::new(read))
+                    .clicked()
+                {
+                    tx.push(Msg::ReadTable);
+                }
+                if ui.button("export").clicked() {
+                    tx.push(Msg::ExportTable);
                 }
             });
+
+            if let Some(rows) = &dialog.table {
+                ui.separator();
+                ui.label(format!(
+                    "{} rows. Choose the field each column is read into:",
+                    rows.rows.len()
+                ));
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    egui::Grid::new("transfer_preview")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (i, header) in rows.headers.iter().enumerate() {
+                                ui.vertical(|ui| {
+                                    ui.strong(header);
+                                    if let Some(field) = edit.mapping.get_mut(i) {
+                                        egui::ComboBox::from_id_salt(("transfer_field", i))
+                                            .selected_text(field.name())
+                                            .show_ui(ui, |ui| {
+                                                for option in table::Field::ALL {
+                                                    ui.selectable_value(
+                                                        field,
+                                                        option,
+                                                        option.name(),
+                                                    );
+                                                }
+                                            });
+                                    }
+                                });
+                            }
+                            ui.end_row();
+                            for row in rows.rows.iter().take(PREVIEW_ROWS) {
+                                for value in row {
+                                    ui.label(value);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("skip rows with");
+                    for dedupe in table::Dedupe::ALL {
+                        ui.radio_value(&mut edit.dedupe, dedupe, dedupe.name());
+                    }
+                });
+
+                // a dry run of the import
+                let plan = table::plan(rows, &edit.mapping, edit.dedupe, &m.tasks);
+                ui.label(format!(
+                    "Would add {} tasks, skipping {} duplicates and {} rows without text.",
+                    plan.tasks.len(),
+                    plan.duplicates,
+                    plan.empty
+                ));
+                if ui
+                    .add_enabled(
+                        !plan.tasks.is_empty(),
+                        egui::Button::new(format!("import {} tasks", plan.tasks.len())),
+                    )
+                    .clicked()
+                {
+                    tx.push(Msg::ImportTable);
+                }
+            }
+
+            if let Some(status) = &dialog.status {
+                ui.separator();
+                ui.label(status);
+            }
+
+            if edit.format != dialog.format
+                || edit.path != dialog.path
+                || edit.mapping != dialog.mapping
+                || edit.dedupe != dialog.dedupe
+            {
+                tx.push(Msg::TransferInput(edit));
+            }
         });
 
-    egui::CentralPanel::default().show(ctx, |ui| {
-        let mut add_task_text_box_has_focus = false;
-        let mut task_edit_box_has_focus = false;
-        let text_edit_id = ui.make_persistent_id("add_task_text_box");
+    if !open {
+        tx.push(Msg::CloseTransfer);
+    }
+}
 
-        ui.vertical_centered(|ui| {
-            ui.heading("cardamom chai");
-            ui.add_space(12.0);
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Directory holding settings, themes and profiles
+    #[arg(long, global = true, env = "CARDAMOM_CHAI_DIR", value_name = "DIR")]
+    data_dir: Option<PathBuf>,
 
-            ui.vertical_centered(|ui| {
-                let mut add_task_text_box = m.add_task_text_box.clone();
+    /// Profile to open, e.g. `work` or `personal`
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
 
-                let response = ui.add(
-                    egui::TextEdit::singleline(&mut add_task_text_box)
-                        .hint_text("Add a task... '/' to search...")
-                        .id(text_edit_id),
-                );
+    /// Override a setting for this session, e.g. `--set hotkeys.search=S`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
 
-                if response.changed() {
-                    match m.filter {
-                        Filter::Search => {
-                            if add_task_text_box.is_empty() {
-                                tx.push(Msg::SetFilter(Filter::All));
+    /// Run a command on the data instead of opening the window
+    #[command(subcommand)]
+    command: Option<cli::Command>,
+}
+
+impl Args {
+    /// The `--set` overrides, and `--profile` as one more.
+    fn settings_overrides(&self) -> Vec<String> {
+        let mut overrides = self.overrides.clone();
+        if let Some(profile) = &self.profile {
+            overrides.push(format!(
+                "profile={}",
+                serde_json::Value::from(profile.as_str())
+            ));
+        }
+        overrides
+    }
+}
+
+static ARGS: OnceLock<Args> = OnceLock::new();
+
+struct SyncState {
+    base_path: PathBuf,
+    tasks_path: PathBuf,
+    notes_path: PathBuf,
+    instance_lock: Option<std::fs::File>,
+    settings_store: Arc<Mutex<Box<dyn Storage>>>,
+    settings_overrides: Vec<String>,
+    themes_path: PathBuf,
+    theme_watch: Option<tokio::task::JoinHandle<()>>,
+    synced: Arc<Mutex<Synced>>,
+    storage: Backend,
+    /// `Settings::todo_txt_path`
+    todo_txt_path: String,
+    /// `Settings::markdown_dir`
+    markdown_dir: String,
+    encrypted: bool,
+    /// Key for the current profile's encrypted databases, once unlocked.
+    key: Option<crypto::Key>,
+    store: Arc<Mutex<Box<dyn Storage>>>,
+    /// Runs storage work off the UI thread, one job at a time; see `queue`.
+    storage_jobs: Option<tokio::sync::mpsc::UnboundedSender<StorageJob>>,
+    /// Shared with `store` when it is the CRDT backend.
+    replica: Option<Arc<Mutex<crdt::Replica>>>,
+    git: Option<git::Recorder>,
+    database_watch: Option<tokio::task::JoinHandle<()>>,
+    /// Where to answer `Msg::IpcRequest`s still being handled.
+    ipc_replies: Arc<Mutex<HashMap<Uuid, oneshot::Sender<ipc::Response>>>>,
+    /// The REST API, while the setting is on.
+    http_server: Option<tokio::task::JoinHandle<()>>,
+}
+
+type StorageJob = Box<dyn FnOnce() + Send>;
+
+/// The tasks and notes as last read from or written to disk, used to tell
+/// our own writes apart from external edits and as the merge base.
+#[derive(Default)]
+struct Synced {
+    tasks: Vec<Task>,
+    notes: String,
+}
+
+enum Cmd {
+    WriteTasks(Vec<Task>),
+    LoadTasks,
+    WriteNotes(String),
+    LoadNotes,
+    ApplyTheme(Theme),
+    LoadThemes,
+    LoadTheme(String),
+    WriteTheme(String, Theme),
+    LoadSettings,
+    WriteSettings(Settings),
+    LoadProfiles,
+    SwitchProfile(String),
+    AcquireLock,
+    WatchDatabase,
+    FinishSyncMerge(Vec<PathBuf>, String),
+    UseStorage(Backend),
+    UseStoragePaths(String, String),
+    UseEncryption(bool),
+    Lock,
+    Unlock(String),
+    UseKey(crypto::Key),
+    ChangePassphrase(String),
+    QueryTasks(Filter, String),
+    ImportJsonDatabase,
+    ExportJsonDatabase(Vec<Task>, String),
+    ImportMarkdownFile(String),
+    ExportMarkdownFile(String, String, usize),
+    ReadTable(table::Format, String),
+    ReadTasks(table::Format, String),
+    ExportTable(table::Format, String, Vec<Task>, String),
+    ExportHtml(String, String, usize),
+    IpcReply(Uuid, ipc::Response),
+    AppendEvent(Event),
+    MigrateStorage(Backend, Vec<Task>, String),
+    UseGit(bool, String),
+    UseHttpApi(bool, u16),
+    RecordHistory(String),
+    LoadHistory,
+    LoadCommit(String),
+}
+
+const TASK_DATABASE_FILENAME: &str = "database.json";
+const NOTES_DATABASE_FILENAME: &str = "notes-database.json";
+const CRDT_DIRNAME: &str = "crdt";
+
+impl SyncState {
+    fn set_profile_paths(&mut self, profile_path: &Path) {
+        std::fs::create_dir_all(profile_path).ok();
+        self.tasks_path = profile_path.join(TASK_DATABASE_FILENAME);
+        self.notes_path = profile_path.join(NOTES_DATABASE_FILENAME);
+        self.open_storage();
+    }
+
+    /// Switches to the storage the settings choose. Opening it reads its
+    /// files, so that is queued like any other storage job.
+    fn open_storage(&mut self) {
+        let dir = self
+            .tasks_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        let backend = self.storage;
+        let replica = (backend == Backend::Crdt).then(|| {
+            let replica = crdt::Replica::new(&dir.join(CRDT_DIRNAME), crdt::replica_id());
+            Arc::new(Mutex::new(replica))
+        });
+        self.replica = replica.clone();
+        let store: Arc<Mutex<Box<dyn Storage>>> =
+            Arc::new(Mutex::new(Box::new(storage::Memory::default())));
+        self.store = store.clone();
+
+        let encrypted = self.encrypted;
+        let key = self.key.clone();
+        let todo_txt_path = self.todo_txt_path();
+        let markdown_dir = self.markdown_dir();
+        self.queue(move || {
+            let opened: Box<dyn Storage> = match backend {
+                Backend::Json if encrypted => match key {
+                    Some(key) => Box::new(crypto::Encrypted::new(&dir, key)),
+                    // nothing is read or written until unlocked
+                    None => Box::new(storage::Memory::default()),
+                },
+                Backend::Json => Box::new(storage::Json::new(&dir)),
+                Backend::Crdt => {
+                    let replica = replica.expect("created for the CRDT backend");
+                    {
+                        let mut replica = replica.lock().unwrap();
+                        std::fs::create_dir_all(replica.dir()).ok();
+                        replica.reload();
+                    }
+                    Box::new(replica)
+                }
+                Backend::Journal => Box::new(journal::Journal::open(&dir)),
+                Backend::TodoTxt => Box::new(todotxt::TodoTxt::new(&todo_txt_path, &dir)),
+                Backend::Markdown => Box::new(markdown::Folder::new(&markdown_dir, &dir)),
+                Backend::Sqlite => {
+                    let path = dir.join(sqlite::SQLITE_FILENAME);
+                    match sqlite::Sqlite::open(&path) {
+                        Ok(sqlite) => Box::new(sqlite),
+                        Err(e) => {
+                            eprintln!("cardamom-chai: {}: {e}", path.display());
+                            Box::new(storage::Memory::default())
+                        }
+                    }
+                }
+            };
+            *store.lock().unwrap() = opened;
+        });
+    }
+
+    /// Runs `job` on the blocking pool once the jobs queued before it are
+    /// done, so storage work keeps the order `update` asked for it in
+    /// without holding up the UI.
+    fn queue(&mut self, job: impl FnOnce() + Send + 'static) {
+        let jobs = self.storage_jobs.get_or_insert_with(|| {
+            let (jobs, mut queued) = tokio::sync::mpsc::unbounded_channel::<StorageJob>();
+            tokio::spawn(async move {
+                while let Some(job) = queued.recv().await {
+                    tokio::task::spawn_blocking(job).await.ok();
+                }
+            });
+            jobs
+        });
+        jobs.send(Box::new(job)).ok();
+    }
+
+    /// Waits for the storage jobs queued so far.
+    async fn storage_settled(&mut self) {
+        let (done, settled) = oneshot::channel();
+        self.queue(move || {
+            done.send(()).ok();
+        });
+        settled.await.ok();
+    }
+
+    fn markdown_dir(&self) -> PathBuf {
+        if self.markdown_dir.is_empty() {
+            self.tasks_path.parent().unwrap_or(Path::new(".")).to_path_buf()
+        } else {
+            PathBuf::from(&self.markdown_dir)
+        }
+    }
+
+    /// Where the tasks are kept, for watching them for outside edits.
+    fn task_files(&self) -> Vec<PathBuf> {
+        match self.storage {
+            Backend::TodoTxt => {
+                let path = self.todo_txt_path();
+                let done_path = path.with_file_name(todotxt::DONE_TXT_FILENAME);
+                vec![path, done_path]
+            }
+            Backend::Markdown => vec![self.markdown_dir().join(markdown::MARKDOWN_FILENAME)],
+            _ => vec![self.tasks_path.clone()],
+        }
+    }
+
+    /// Where the notes are kept, for watching them for outside edits.
+    fn notes_file(&self) -> PathBuf {
+        match self.storage {
+            Backend::Markdown => self.markdown_dir().join(markdown::NOTES_FILENAME),
+            _ => self.notes_path.clone(),
+        }
+    }
+
+    fn todo_txt_path(&self) -> PathBuf {
+        if self.todo_txt_path.is_empty() {
+            self.tasks_path.with_file_name(todotxt::TODO_TXT_FILENAME)
+        } else {
+            PathBuf::from(&self.todo_txt_path)
+        }
+    }
+
+    /// Writes are dropped while another instance owns the data directory.
+    fn read_only(&self) -> bool {
+        self.instance_lock.is_none()
+    }
+
+    fn locked(&self) -> bool {
+        self.encrypted && self.key.is_none() && self.storage == Backend::Json
+    }
+}
+
+impl Drop for SyncState {
+    /// Gives up the single-instance lock when the app shuts down, so the
+    /// next launch does not take it for one left behind by a crash.
+    fn drop(&mut self) {
+        if let Some(file) = self.instance_lock.take() {
+            lock::release(file);
+        }
+    }
+}
+
+fn sync_state_init() -> SyncState {
+    let args = ARGS.get();
+    let path = match args.and_then(|args| args.data_dir.clone()) {
+        Some(path) => path,
+        None => {
+            let mut path = data_dir().expect("no data dir found");
+            path.push("cardamom-chai");
+            path
+        }
+    };
+    std::fs::create_dir_all(&path).ok();
+    let tasks_path = path.join(TASK_DATABASE_FILENAME);
+    let notes_path = path.join(NOTES_DATABASE_FILENAME);
+    let settings_store: Box<dyn Storage> = Box::new(storage::Json::new(&path));
+    let settings_store = Arc::new(Mutex::new(settings_store));
+    let themes_path = path.join("themes");
+    let settings_overrides = args.map(Args::settings_overrides).unwrap_or_default();
+
+    SyncState {
+        base_path: path,
+        tasks_path,
+        notes_path,
+        instance_lock: None,
+        settings_store,
+        settings_overrides,
+        themes_path,
+        theme_watch: None,
+        synced: Arc::default(),
+        storage: Backend::default(),
+        todo_txt_path: String::new(),
+        markdown_dir: String::new(),
+        encrypted: false,
+        key: None,
+        store: Arc::new(Mutex::new(Box::new(storage::Memory::default()))),
+        storage_jobs: None,
+        replica: None,
+        git: None,
+        database_watch: None,
+        ipc_replies: Arc::default(),
+        http_server: None,
+    }
+}
+
+fn run_cmd(cmd: Cmd, sync_state: &mut SyncState, tx: chai_tea::ChaiSender<Msg>) {
+    match cmd {
+        Cmd::WriteTasks(_)
+        | Cmd::WriteNotes(_)
+        | Cmd::WriteSettings(_)
+        | Cmd::RecordHistory(_)
+        | Cmd::AppendEvent(_)
+        | Cmd::ExportJsonDatabase(..)
+            if sync_state.read_only() || sync_state.locked() => {}
+
+        // exports leave the database alone, so read-only instances write them too
+        Cmd::ExportTable(..) | Cmd::ExportHtml(..) if sync_state.locked() => {
+            let status = "Unlock the profile to export it.".to_string();
+            tx.send(Msg::TransferStatus(status)).ok();
+        }
+        Cmd::ExportMarkdownFile(..) if sync_state.locked() => {
+            let status = "Unlock the profile to export it.".to_string();
+            tx.send(Msg::MarkdownStatus(status)).ok();
+        }
+
+        Cmd::WriteTasks(tasks) => {
+            sync_state.synced.lock().unwrap().tasks = tasks.clone();
+            let store = sync_state.store.clone();
+            sync_state.queue(move || {
+                if let Err(e) = store.lock().unwrap().save_tasks(&tasks) {
+                    eprintln!("cardamom-chai: saving tasks: {e}");
+                }
+            });
+        }
+
+        Cmd::LoadTasks => {
+            let store = sync_state.store.clone();
+            let synced = sync_state.synced.clone();
+            sync_state.queue(move || {
+                let tasks = store.lock().unwrap().load_tasks().unwrap_or_else(|e| {
+                    eprintln!("cardamom-chai: loading tasks: {e}");
+                    vec![]
+                });
+                synced.lock().unwrap().tasks = tasks.clone();
+                tx.send(Msg::LoadedTasks(tasks)).ok();
+            });
+        }
+
+        Cmd::WriteNotes(notes) => {
+            sync_state.synced.lock().unwrap().notes = notes.clone();
+            let store = sync_state.store.clone();
+            sync_state.queue(move || {
+                if let Err(e) = store.lock().unwrap().save_notes(&notes) {
+                    eprintln!("cardamom-chai: saving notes: {e}");
+                }
+            });
+        }
+
+        Cmd::LoadNotes => {
+            let store = sync_state.store.clone();
+            let synced = sync_state.synced.clone();
+            sync_state.queue(move || {
+                let notes = store.lock().unwrap().load_notes().unwrap_or_else(|e| {
+                    eprintln!("cardamom-chai: loading notes: {e}");
+                    String::new()
+                });
+                synced.lock().unwrap().notes = notes.clone();
+                tx.send(Msg::LoadedNotes(notes)).ok();
+            });
+        }
+
+        Cmd::ApplyTheme(theme) => {
+            tx.with_ctx(|ctx| {
+                ctx.set_visuals(theme.visuals());
+                let mut style = (*ctx.style()).clone();
+                style.text_styles = theme.text_styles();
+                ctx.set_style(style);
+                ctx.request_repaint();
+            });
+        }
+
+        Cmd::LoadThemes => {
+            let themes_path = sync_state.themes_path.clone();
+            tokio::spawn(async move {
+                theme::install_bundled(&themes_path);
+                tx.send(Msg::LoadedThemes(theme::list(&themes_path))).ok();
+            });
+        }
+
+        Cmd::LoadTheme(name) => {
+            // keep polling the chosen file so edits to it are picked up live
+            let path_load = theme::theme_path(&sync_state.themes_path, &name);
+            if let Some(watch) = sync_state.theme_watch.take() {
+                watch.abort();
+            }
+            sync_state.theme_watch = Some(tokio::spawn(async move {
+                let mut last_modified = None;
+                loop {
+                    let modified = modified_time(&path_load).await;
+                    if modified != last_modified {
+                        last_modified = modified;
+                        let theme = match tokio::fs::read_to_string(&path_load).await {
+                            Ok(data) => serde_json::from_str(&data).ok(),
+                            Err(_) => None,
+                        };
+                        if let Some(theme) = theme
+                            && tx.send(Msg::LoadedTheme(theme)).is_err()
+                        {
+                            break;
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }));
+        }
+
+        Cmd::WriteTheme(name, theme) => {
+            let themes_path = sync_state.themes_path.clone();
+            tokio::spawn(async move {
+                let json = serde_json::to_string_pretty(&theme).expect("failed to serialize");
+                tokio::fs::write(theme::theme_path(&themes_path, &name), json)
+                    .await
+                    .ok();
+                tx.send(Msg::LoadedThemes(theme::list(&themes_path))).ok();
+            });
+        }
+
+        Cmd::LoadSettings => {
+            let settings_store = sync_state.settings_store.clone();
+            let overrides = sync_state.settings_overrides.clone();
+            sync_state.queue(move || {
+                let settings = match settings_store.lock().unwrap().load_settings() {
+                    Ok(settings) => settings.unwrap_or_default(),
+                    Err(e) => {
+                        eprintln!("cardamom-chai: loading settings: {e}");
+                        Settings::default()
+                    }
+                };
+                tx.send(Msg::LoadedSettings(settings, overrides)).ok();
+            });
+        }
+
+        Cmd::LoadProfiles => {
+            let base_path = sync_state.base_path.clone();
+            tokio::spawn(async move {
+                tx.send(Msg::LoadedProfiles(profiles::list(&base_path)))
+                    .ok();
+            });
+        }
+
+        Cmd::SwitchProfile(profile) => {
+            if let Some(watch) = sync_state.database_watch.take() {
+                watch.abort();
+            }
+            // every profile has its own passphrase
+            sync_state.key = None;
+            let base_path = sync_state.base_path.clone();
+            sync_state.set_profile_paths(&profiles::profile_dir(&base_path, &profile));
+            tokio::spawn(async move {
+                tx.send(Msg::LoadedProfiles(profiles::list(&base_path)))
+                    .ok();
+                tx.send(Msg::ProfileSwitched(profile)).ok();
+            });
+        }
+
+        Cmd::WatchDatabase => {
+            if let Some(watch) = sync_state.database_watch.take() {
+                watch.abort();
+            }
+            let tasks_path = sync_state.tasks_path.clone();
+            let notes_path = sync_state.notes_file();
+            let synced = sync_state.synced.clone();
+            let replica = sync_state.replica.clone();
+            let storage = sync_state.storage;
+            let watched = sync_state.task_files();
+            // encrypted files are compared by their plaintext
+            let key = match &sync_state.key {
+                Some(key) if sync_state.encrypted && storage == Backend::Json => Some(key.clone()),
+                _ => None,
+            };
+            sync_state.database_watch = Some(tokio::spawn(async move {
+                let mut tasks_modified = vec![];
+                for path in &watched {
+                    tasks_modified.push(modified_time(path).await);
+                }
+                let mut notes_modified = modified_time(&notes_path).await;
+                let mut replicas_modified = match &replica {
+                    Some(replica) => other_replicas_modified(replica).await,
+                    None => None,
+                };
+                let mut reported_copies: Vec<PathBuf> = vec![];
+                loop {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+
+                    if let Some(replica) = &replica {
+                        let modified = other_replicas_modified(replica).await;
+                        if modified != replicas_modified {
+                            replicas_modified = modified;
+                            let (tasks_base, tasks, notes_base, notes) = {
+                                let mut replica = replica.lock().unwrap();
+                                let tasks_base = replica.tasks();
+                                let notes_base = replica.notes();
+                                replica.reload();
+                                (tasks_base, replica.tasks(), notes_base, replica.notes())
+                            };
+                            if (tasks != tasks_base
+                                && tx.send(Msg::TasksChangedOnDisk(tasks_base, tasks)).is_err())
+                                || (notes != notes_base
+                                    && tx.send(Msg::NotesChangedOnDisk(notes_base, notes)).is_err())
+                            {
+                                break;
                             }
                         }
-                        _ => {
-                            if add_task_text_box.starts_with('/') {
-                                tx.push(Msg::SetFilter(Filter::Search));
+                    }
+
+                    let dir = tasks_path.parent().unwrap_or(Path::new("."));
+                    let mut copies = sync::ConflictCopies::default();
+                    reported_copies.retain(|path| path.exists());
+                    for path in sync::find_conflict_copies(dir, TASK_DATABASE_FILENAME) {
+                        if reported_copies.contains(&path) {
+                            continue;
+                        }
+                        let copy = match tokio::fs::read_to_string(&path).await {
+                            Ok(data) => serde_json::from_str::<Vec<Task>>(&data).ok(),
+                            Err(_) => None,
+                        };
+                        if let Some(copy) = copy {
+                            reported_copies.push(path.clone());
+                            copies.tasks.push((path, copy));
+                        }
+                    }
+                    for path in sync::find_conflict_copies(dir, NOTES_DATABASE_FILENAME) {
+                        if reported_copies.contains(&path) {
+                            continue;
+                        }
+                        if let Ok(copy) = tokio::fs::read_to_string(&path).await {
+                            reported_copies.push(path.clone());
+                            copies.notes.push((path, copy));
+                        }
+                    }
+                    if !copies.is_empty() && tx.send(Msg::SyncConflicts(copies)).is_err() {
+                        break;
+                    }
+
+                    let mut modified = vec![];
+                    for path in &watched {
+                        modified.push(modified_time(path).await);
+                    }
+                    if modified != tasks_modified {
+                        tasks_modified = modified;
+                        // a half-written file fails to parse; its final write bumps the time again
+                        let mut files = vec![];
+                        for path in &watched {
+                            let data = tokio::fs::read_to_string(path).await.ok();
+                            files.push(data.and_then(|data| {
+                                plaintext(key.as_ref(), TASK_DATABASE_FILENAME, data)
+                            }));
+                        }
+                        let known = synced.lock().unwrap().tasks.clone();
+                        let theirs = parse_task_files(storage, &files, &known);
+                        if let Some(theirs) = theirs {
+                            let base = {
+                                let mut synced = synced.lock().unwrap();
+                                (synced.tasks != theirs)
+                                    .then(|| std::mem::replace(&mut synced.tasks, theirs.clone()))
+                            };
+                            if let Some(base) = base
+                                && tx.send(Msg::TasksChangedOnDisk(base, theirs)).is_err()
+                            {
+                                break;
                             }
                         }
                     }
 
-                    tx.push(Msg::TextInput(add_task_text_box));
+                    let modified = modified_time(&notes_path).await;
+                    if modified != notes_modified {
+                        notes_modified = modified;
+                        if let Ok(data) = tokio::fs::read_to_string(&notes_path).await
+                            && let Some((base, theirs)) =
+                                notes_changed_on_disk(&synced, data, key.as_ref())
+                            && tx.send(Msg::NotesChangedOnDisk(base, theirs)).is_err()
+                        {
+                            break;
+                        }
+                    }
+                }
+            }));
+        }
+
+        Cmd::FinishSyncMerge(paths, report) => {
+            let log_path = sync_state
+                .tasks_path
+                .with_file_name(sync::MERGE_LOG_FILENAME);
+            // queued, so the copies are only dropped once the merged result
+            // is saved
+            sync_state.queue(move || {
+                for path in paths {
+                    std::fs::remove_file(path).ok();
+                }
+                let entry = format!("== {} ==\n{report}\n\n", Utc::now().to_rfc3339());
+                let mut log = std::fs::read_to_string(&log_path).unwrap_or_default();
+                log.push_str(&entry);
+                std::fs::write(&log_path, log).ok();
+            });
+        }
+
+        Cmd::UseStorage(storage) => {
+            if sync_state.storage != storage {
+                sync_state.storage = storage;
+                sync_state.open_storage();
+            }
+        }
+
+        Cmd::UseStoragePaths(todo_txt_path, markdown_dir) => {
+            sync_state.todo_txt_path = todo_txt_path;
+            sync_state.markdown_dir = markdown_dir;
+        }
+
+        Cmd::MigrateStorage(storage, tasks, notes) => {
+            sync_state.storage = storage;
+            sync_state.open_storage();
+            let read_only = sync_state.read_only();
+            let store = sync_state.store.clone();
+            let synced = sync_state.synced.clone();
+            sync_state.queue(move || {
+                let mut store = store.lock().unwrap();
+                let stored = store
+                    .load_tasks()
+                    .and_then(|tasks| Ok((tasks, store.load_notes()?)));
+                let (tasks, notes) = match stored {
+                    Ok((stored_tasks, stored_notes))
+                        if read_only
+                            || (store.keeps_existing()
+                                && !(stored_tasks.is_empty() && stored_notes.is_empty())) =>
+                    {
+                        (stored_tasks, stored_notes)
+                    }
+                    _ => {
+                        let saved = store.save_tasks(&tasks).and(store.save_notes(&notes));
+                        if let Err(e) = saved {
+                            eprintln!("cardamom-chai: migrating to {}: {e}", storage.name());
+                        }
+                        (tasks, notes)
+                    }
+                };
+                {
+                    let mut synced = synced.lock().unwrap();
+                    synced.tasks = tasks.clone();
+                    synced.notes = notes.clone();
+                }
+                tx.send(Msg::LoadedTasks(tasks)).ok();
+                tx.send(Msg::LoadedNotes(notes)).ok();
+            });
+        }
+
+        Cmd::UseEncryption(encrypted) => {
+            sync_state.encrypted = encrypted;
+            sync_state.open_storage();
+        }
+
+        Cmd::Lock => {
+            if let Some(watch) = sync_state.database_watch.take() {
+                watch.abort();
+            }
+            sync_state.key = None;
+            sync_state.open_storage();
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            tx.send(Msg::Locked(crypto::is_encrypted(dir))).ok();
+        }
+
+        Cmd::Unlock(passphrase) => {
+            let dir = sync_state
+                .tasks_path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                tx.send(Msg::Unlocked(crypto::unlock(&dir, &passphrase)))
+                    .ok();
+            });
+        }
+
+        Cmd::UseKey(key) => {
+            sync_state.key = Some(key);
+            sync_state.open_storage();
+        }
+
+        Cmd::ChangePassphrase(passphrase) => {
+            tokio::task::spawn_blocking(move || {
+                tx.send(Msg::PassphraseChanged(crypto::Key::generate(&passphrase)))
+                    .ok();
+            });
+        }
+
+        Cmd::QueryTasks(filter, search) => {
+            let store = sync_state.store.clone();
+            sync_state.queue(move || {
+                if let Some(ids) = store.lock().unwrap().query(filter, &search) {
+                    tx.send(Msg::QueriedTasks(filter, search, ids)).ok();
                 }
+            });
+        }
 
-                if response.lost_focus()
-                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
-                    && !matches!(m.filter, Filter::Search)
+        Cmd::ImportJsonDatabase => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let mut json = storage::Json::new(dir);
+            sync_state.queue(move || {
+                match json
+                    .load_tasks()
+                    .and_then(|tasks| Ok((tasks, json.load_notes()?)))
                 {
-                    tx.push(Msg::Add);
-                    ui.memory_mut(|mem| mem.request_focus(text_edit_id));
+                    Ok((tasks, notes)) => {
+                        tx.send(Msg::ImportedJsonDatabase(tasks, notes)).ok();
+                    }
+                    Err(e) => eprintln!("cardamom-chai: importing {TASK_DATABASE_FILENAME}: {e}"),
                 }
-
-                add_task_text_box_has_focus = response.has_focus();
             });
+        }
 
-            ui.add_space(12.0);
+        Cmd::ExportJsonDatabase(tasks, notes) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let mut json = storage::Json::new(dir);
+            sync_state.queue(move || {
+                if let Err(e) = json.save_tasks(&tasks).and(json.save_notes(&notes)) {
+                    eprintln!("cardamom-chai: exporting {TASK_DATABASE_FILENAME}: {e}");
+                }
+            });
+        }
 
-            egui::ScrollArea::vertical()
-                .auto_shrink(false)
-                .show(ui, |ui| {
-                    for task in m.tasks.iter().rev().filter(|t| match &m.filter {
-                        Filter::All => true,
-                        Filter::Active => matches!(t.state, TaskState::Chosen),
-                        Filter::Pending => !t.done,
-                        Filter::Uncertain => matches!(t.state, TaskState::Uncertain),
-                        Filter::Search => fuzzy_match(
-                            &t.task_text.to_lowercase(),
-                            m.add_task_text_box.trim_start_matches('/'),
-                        ),
-                        Filter::Done => t.done,
-                    }) {
-                        ui.horizontal_wrapped(|ui| {
-                            let mut checked = task.done;
+        Cmd::ImportMarkdownFile(path) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let file = dir.join(&path);
+            sync_state.queue(move || {
+                let result =
+                    std::fs::read_to_string(&file).map_err(|e| format!("{}: {e}", file.display()));
+                tx.send(Msg::ImportedMarkdown(path, result)).ok();
+            });
+        }
 
-                            let asterisk = task.task_text.ends_with('*');
-                            let trimmed_text = task.task_text.trim_end_matches('*');
-                            let text = if checked {
-                                RichText::new(trimmed_text).strikethrough().weak()
-                            } else {
-                                match task.state {
-                                    TaskState::Normal => RichText::new(trimmed_text),
-                                    TaskState::Chosen => RichText::new(trimmed_text)
-                                        .color(egui::Color32::from_rgb(32, 159, 181))
-                                        .underline(),
-                                    TaskState::Uncertain => {
-                                        RichText::new(format!("{}?", trimmed_text))
-                                            .color(egui::Color32::from_rgb(234, 118, 203))
-                                    }
-                                }
-                            };
+        Cmd::ExportMarkdownFile(path, text, count) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let path = dir.join(path);
+            sync_state.queue(move || {
+                let status = match std::fs::write(&path, text) {
+                    Ok(()) => format!("Exported {count} tasks to {}.", path.display()),
+                    Err(e) => format!("{}: {e}", path.display()),
+                };
+                tx.send(Msg::MarkdownStatus(status)).ok();
+            });
+        }
 
-                            if m.edit_tasks.contains(&task.task_id) {
-                                let mut edit_task_text_box = task.task_text.clone();
-                                let _ = ui.checkbox(&mut checked, "");
-                                let response = ui.add(
-                                    egui::TextEdit::singleline(&mut edit_task_text_box)
-                                        .desired_width(700.0),
-                                );
+        Cmd::ReadTable(format, path) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let path = dir.join(path);
+            sync_state.queue(move || {
+                let result = std::fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| table::parse(format, &data))
+                    .map_err(|e| format!("{}: {e}", path.display()));
+                tx.send(Msg::LoadedTable(result)).ok();
+            });
+        }
 
-                                if response.changed() {
-                                    tx.push(Msg::EditInput(task.task_id, edit_task_text_box));
-                                }
+        Cmd::ReadTasks(format, path) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let file = dir.join(&path);
+            sync_state.queue(move || {
+                let result = std::fs::read_to_string(&file)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| table::parse_tasks(format, &data))
+                    .map_err(|e| format!("{}: {e}", file.display()));
+                tx.send(Msg::ImportedTasks(path, result)).ok();
+            });
+        }
 
-                                if response.lost_focus()
-                                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
-                                {
-                                    tx.push(Msg::EditDone(task.task_id));
-                                }
-                                task_edit_box_has_focus |= response.has_focus();
-                            } else {
-                                let check_response = ui.checkbox(&mut checked, text);
+        Cmd::ExportTable(format, path, tasks, notes) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let path = dir.join(path);
+            sync_state.queue(move || {
+                let status = match table::export(format, &tasks, &notes)
+                    .and_then(|data| std::fs::write(&path, data).map_err(|e| e.to_string()))
+                {
+                    Ok(()) => format!("Exported {} tasks to {}.", tasks.len(), path.display()),
+                    Err(e) => format!("{}: {e}", path.display()),
+                };
+                tx.send(Msg::TransferStatus(status)).ok();
+            });
+        }
 
-                                if check_response.changed() {
-                                    tx.push(Msg::CheckBox(task.task_id, checked));
-                                }
+        Cmd::ExportHtml(path, page, count) => {
+            let dir = sync_state.tasks_path.parent().unwrap_or(Path::new("."));
+            let path = dir.join(path);
+            sync_state.queue(move || {
+                let status = match std::fs::write(&path, page) {
+                    Ok(()) => format!("Exported {count} tasks to {}.", path.display()),
+                    Err(e) => format!("{}: {e}", path.display()),
+                };
+                tx.send(Msg::TransferStatus(status)).ok();
+            });
+        }
 
-                                if check_response.middle_clicked() {
-                                    tx.push(Msg::Edit(task.task_id));
-                                }
+        Cmd::AppendEvent(event) => {
+            let store = sync_state.store.clone();
+            sync_state.queue(move || {
+                if let Err(e) = store.lock().unwrap().append(&event) {
+                    eprintln!("cardamom-chai: {e}");
+                }
+            });
+        }
 
-                                if check_response.secondary_clicked() {
-                                    tx.push(Msg::CycleTaskState(task.task_id));
-                                }
+        Cmd::UseGit(enabled, remote) => {
+            sync_state.git =
+                enabled.then(|| git::Recorder::new(sync_state.base_path.clone(), remote));
+        }
 
-                                if (checked || matches!(task.state, TaskState::Uncertain))
-                                    && ui.button("🗑").clicked()
-                                {
-                                    tx.push(Msg::Delete(task.task_id));
-                                }
+        Cmd::RecordHistory(message) => {
+            if let Some(git) = &mut sync_state.git {
+                git.record(message);
+            }
+        }
 
-                                if checked && !asterisk && ui.button("🔁").clicked() {
-                                    tx.push(Msg::Reschedule(task.task_text.clone()));
-                                }
+        Cmd::LoadHistory => {
+            let base_path = sync_state.base_path.clone();
+            tokio::spawn(async move {
+                tx.send(Msg::LoadedHistory(git::log(&base_path, 500).await))
+                    .ok();
+            });
+        }
 
-                                if checked && asterisk && ui.button("⟲").clicked() {
-                                    tx.push(Msg::RescheduleActive(task.task_text.clone()));
-                                    tx.push(Msg::CycleTaskState(task.task_id));
-                                }
+        Cmd::LoadCommit(hash) => {
+            let base_path = sync_state.base_path.clone();
+            let relative = |path: &Path| {
+                path.strip_prefix(&base_path)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            };
+            let tasks_file = relative(&sync_state.tasks_path);
+            let notes_file = relative(&sync_state.notes_file());
+            let crdt_dir = relative(&sync_state.tasks_path.with_file_name(CRDT_DIRNAME));
+            let journal_file = relative(
+                &sync_state
+                    .tasks_path
+                    .with_file_name(journal::JOURNAL_FILENAME),
+            );
+            let sqlite_file = relative(
+                &sync_state
+                    .tasks_path
+                    .with_file_name(sqlite::SQLITE_FILENAME),
+            );
+            let snapshot_file = relative(
+                &sync_state
+                    .tasks_path
+                    .with_file_name(journal::SNAPSHOT_FILENAME),
+            );
+            let task_files: Vec<String> = sync_state
+                .task_files()
+                .iter()
+                .map(|path| relative(path))
+                .collect();
+            let storage = sync_state.storage;
+            let key = sync_state.key.clone();
+            tokio::spawn(async move {
+                // versions encrypted with an earlier passphrase read as empty
+                let open = |filename: &str, data: String| match &key {
+                    Some(key) => key.open(filename, &data).ok(),
+                    None => Some(data),
+                };
+                let (tasks, notes) = match storage {
+                    Backend::Json => {
+                        let tasks = git::show(&base_path, &hash, &tasks_file)
+                            .await
+                            .and_then(|data| open(TASK_DATABASE_FILENAME, data))
+                            .and_then(|data| serde_json::from_str(&data).ok())
+                            .unwrap_or_default();
+                        let notes = git::show(&base_path, &hash, &notes_file)
+                            .await
+                            .and_then(|data| open(NOTES_DATABASE_FILENAME, data))
+                            .unwrap_or_default();
+                        (tasks, notes)
+                    }
+                    Backend::Crdt => {
+                        let mut doc = crdt::Doc::default();
+                        for file in git::list_files(&base_path, &hash, &crdt_dir).await {
+                            if let Some(replica) = git::show(&base_path, &hash, &file)
+                                .await
+                                .and_then(|data| serde_json::from_str::<crdt::Doc>(&data).ok())
+                            {
+                                doc.merge(&replica);
                             }
-                        });
+                        }
+                        (doc.tasks(), doc.notes())
                     }
-                });
-        });
-        //hotkeys
-        if !add_task_text_box_has_focus
-            && !task_edit_box_has_focus
-            && !matches!(m.notes_state, NotesState::Edit)
-        {
-            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                ui.memory_mut(|mem| mem.request_focus(text_edit_id));
-            }
+                    Backend::Journal => {
+                        let snapshot = git::show(&base_path, &hash, &snapshot_file)
+                            .await
+                            .unwrap_or_default();
+                        let log = git::show(&base_path, &hash, &journal_file)
+                            .await
+                            .unwrap_or_default();
+                        journal::Journal::replay(&snapshot, &log)
+                    }
+                    Backend::Sqlite => {
+                        // SQLite needs a file to open, so check the old version out to a temporary one
+                        let path = std::env::temp_dir()
+                            .join(format!("cardamom-chai-{}.sqlite", Uuid::new_v4()));
+                        let mut data = (vec![], String::new());
+                        if let Some(bytes) = git::show_bytes(&base_path, &hash, &sqlite_file).await
+                            && tokio::fs::write(&path, bytes).await.is_ok()
+                        {
+                            if let Ok(mut sqlite) = sqlite::Sqlite::open(&path) {
+                                data = (
+                                    sqlite.load_tasks().unwrap_or_default(),
+                                    sqlite.load_notes().unwrap_or_default(),
+                                );
+                            }
+                            tokio::fs::remove_file(&path).await.ok();
+                        }
+                        data
+                    }
+                    // files outside the data dir have no history
+                    Backend::TodoTxt | Backend::Markdown => {
+                        let mut files = vec![];
+                        for file in &task_files {
+                            files.push(git::show(&base_path, &hash, file).await);
+                        }
+                        let notes = git::show(&base_path, &hash, &notes_file)
+                            .await
+                            .unwrap_or_default();
+                        let tasks = parse_task_files(storage, &files, &[]).unwrap_or_default();
+                        (tasks, notes)
+                    }
+                };
+                tx.send(Msg::LoadedCommit(hash, tasks, notes)).ok();
+            });
+        }
 
-            if ui.input(|i| i.key_pressed(egui::Key::Slash)) {
-                if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), text_edit_id) {
-                    let ccursor = egui::text::CCursor::new(1);
-                    state
-                        .cursor
-                        .set_char_range(Some(egui::text::CCursorRange::one(ccursor)));
-                    state.store(ui.ctx(), text_edit_id);
-                    ui.ctx().memory_mut(|mem| mem.request_focus(text_edit_id)); // give focus back to the [`TextEdit`].
+        Cmd::AcquireLock => {
+            if sync_state.read_only() {
+                let status = match lock::acquire(&sync_state.base_path) {
+                    Ok(file) => {
+                        sync_state.instance_lock = Some(file);
+                        lock::Status::Owned
+                    }
+                    Err(status) => status,
+                };
+                tx.send(Msg::InstanceLock(status)).ok();
+                // only the instance that writes takes requests
+                if status == lock::Status::Owned {
+                    let dir = sync_state.base_path.clone();
+                    let handle = ipc_handler(sync_state, tx);
+                    tokio::spawn(async move {
+                        if let Err(e) = ipc::listen(&dir, handle).await {
+                            eprintln!("cardamom-chai: {e}");
+                        }
+                    });
                 }
-                tx.push(Msg::TextInput("/".to_string()));
-                tx.push(Msg::SetFilter(Filter::Search));
-            }
-
-            if ui.input(|i| i.key_pressed(egui::Key::A)) {
-                tx.push(Msg::SetFilter(Filter::All));
             }
+        }
 
-            if ui.input(|i| i.key_pressed(egui::Key::F)) {
-                tx.push(Msg::SetFilter(Filter::Active));
+        Cmd::UseHttpApi(enabled, port) => {
+            if let Some(server) = sync_state.http_server.take() {
+                server.abort();
             }
-
-            if ui.input(|i| i.key_pressed(egui::Key::P)) {
-                tx.push(Msg::SetFilter(Filter::Pending));
+            // like the socket, only the instance that writes serves the api
+            if !enabled || sync_state.read_only() {
+                return;
             }
+            let token = match http::token(&sync_state.base_path) {
+                Ok(token) => token,
+                Err(e) => {
+                    eprintln!("cardamom-chai: http api token: {e}");
+                    return;
+                }
+            };
+            let handle = ipc_handler(sync_state, tx);
+            sync_state.http_server = Some(tokio::spawn(async move {
+                if let Err(e) = http::serve(port, token, handle).await {
+                    eprintln!("cardamom-chai: {e}");
+                }
+            }));
+        }
 
-            if ui.input(|i| i.key_pressed(egui::Key::U)) {
-                tx.push(Msg::SetFilter(Filter::Uncertain));
+        Cmd::IpcReply(id, response) => {
+            if let Some(reply) = sync_state.ipc_replies.lock().unwrap().remove(&id) {
+                reply.send(response).ok();
             }
+        }
 
-            if ui.input(|i| i.key_pressed(egui::Key::D)) {
-                tx.push(Msg::SetFilter(Filter::Done));
-            }
+        Cmd::WriteSettings(settings) => {
+            let settings_store = sync_state.settings_store.clone();
+            sync_state.queue(move || {
+                if let Err(e) = settings_store.lock().unwrap().save_settings(&settings) {
+                    eprintln!("cardamom-chai: saving settings: {e}");
+                }
+            });
         }
-    });
+    }
 }
 
-struct SyncState {
-    tasks_path: PathBuf,
-    notes_path: PathBuf,
+/// Passes requests from other programs to `update`, keeping where to
+/// answer each until its `Cmd::IpcReply`.
+fn ipc_handler(
+    sync_state: &SyncState,
+    tx: chai_tea::ChaiSender<Msg>,
+) -> impl Fn(ipc::Request, oneshot::Sender<ipc::Response>) + Send + Sync + 'static {
+    let replies = sync_state.ipc_replies.clone();
+    move |request, reply| {
+        let id = Uuid::new_v4();
+        replies.lock().unwrap().insert(id, reply);
+        tx.send(Msg::IpcRequest(id, request)).ok();
+    }
 }
 
-enum Cmd {
-    WriteTasks(Vec<Task>),
-    LoadTasks,
-    WriteNotes(String),
-    LoadNotes,
-    InitTheme,
+/// Latest modification time among the replica files written by other devices.
+async fn other_replicas_modified(replica: &Mutex<crdt::Replica>) -> Option<SystemTime> {
+    let (dir, own) = {
+        let replica = replica.lock().unwrap();
+        (replica.dir().to_path_buf(), replica.path())
+    };
+    let mut latest = None;
+    let mut entries = tokio::fs::read_dir(dir).await.ok()?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.path() == own {
+            continue;
+        }
+        let modified = entry.metadata().await.and_then(|meta| meta.modified()).ok();
+        latest = latest.max(modified);
+    }
+    latest
 }
 
-fn sync_state_init() -> SyncState {
-    let task_database_filename = "database.json";
-    let notes_database_filename = "notes-database.json";
-    let mut path = data_dir().expect("no data dir found");
-    path.push("cardamom-chai");
-    std::fs::create_dir_all(&path).ok();
-    let tasks_path = path.join(task_database_filename);
-    let notes_path = path.join(notes_database_filename);
-
-    SyncState {
-        tasks_path,
-        notes_path,
+/// The tasks in the files `SyncState::task_files` names, as read, or
+/// `None` if they cannot be parsed.
+fn parse_task_files(storage: Backend, files: &[Option<String>], known: &[Task]) -> Option<Vec<Task>> {
+    let text = |i: usize| files.get(i).cloned().flatten().unwrap_or_default();
+    match storage {
+        Backend::TodoTxt => Some(todotxt::parse(&text(0), &text(1), known)),
+        Backend::Markdown => Some(markdown::parse(&text(0), known)),
+        _ => serde_json::from_str(files.first()?.as_ref()?).ok(),
     }
 }
 
-fn run_cmd(cmd: Cmd, sync_state: &mut SyncState, tx: chai_tea::ChaiSender<Msg>) {
-    match cmd {
-        Cmd::WriteTasks(tasks) => {
-            let path_write = sync_state.tasks_path.clone();
-            tokio::spawn(async move {
-                let json = serde_json::to_string_pretty(&tasks).expect("failed to serialize");
-                tokio::fs::write(path_write, json).await.ok();
-            });
-        }
-
-        Cmd::LoadTasks => {
-            let path_load = sync_state.tasks_path.clone();
-            tokio::spawn(async move {
-                let tasks = match tokio::fs::read_to_string(&path_load).await {
-                    Ok(data) => serde_json::from_str(&data).unwrap_or_else(|_| vec![]),
-                    Err(_) => vec![],
-                };
-                tx.send(Msg::LoadedTasks(tasks)).ok();
-            });
-        }
-
-        Cmd::WriteNotes(notes) => {
-            let path_write = sync_state.notes_path.clone();
-            tokio::spawn(async move {
-                tokio::fs::write(path_write, notes).await.ok();
-            });
-        }
-
-        Cmd::LoadNotes => {
-            let path_load = sync_state.notes_path.clone();
-            tokio::spawn(async move {
-                let notes = (tokio::fs::read_to_string(&path_load).await).unwrap_or_default();
-
-                tx.send(Msg::LoadedNotes(notes)).ok();
-            });
-        }
+/// The contents of a database file as its storage reads them: decrypted
+/// with `key` for an encrypted profile, or `None` when that fails.
+fn plaintext(key: Option<&crypto::Key>, filename: &str, data: String) -> Option<String> {
+    match key {
+        Some(key) => key.open(filename, &data).ok(),
+        None => Some(data),
+    }
+}
 
-        Cmd::InitTheme => {
-            tx.with_ctx(|ctx| {
-                let visuals = egui::Visuals::light();
-                ctx.set_visuals(visuals);
-                let mut style = (*ctx.style()).clone();
-                style.text_styles = [
-                    (
-                        egui::TextStyle::Heading,
-                        egui::FontId::new(24.0, egui::FontFamily::Proportional),
-                    ),
-                    (
-                        egui::TextStyle::Body,
-                        egui::FontId::new(15.0, egui::FontFamily::Proportional),
-                    ),
-                    (
-                        egui::TextStyle::Monospace,
-                        egui::FontId::new(14.0, egui::FontFamily::Monospace),
-                    ),
-                    (
-                        egui::TextStyle::Button,
-                        egui::FontId::new(15.0, egui::FontFamily::Proportional),
-                    ),
-                    (
-                        egui::TextStyle::Small,
-                        egui::FontId::new(10.0, egui::FontFamily::Proportional),
-                    ),
-                ]
-                .into();
-                ctx.set_style(style);
-                ctx.request_repaint();
-            });
-        }
+/// The notes last synced and the ones in `data`, the contents of the notes
+/// file, when those differ. The new notes then count as synced.
+fn notes_changed_on_disk(
+    synced: &Mutex<Synced>,
+    data: String,
+    key: Option<&crypto::Key>,
+) -> Option<(String, String)> {
+    let theirs = plaintext(key, NOTES_DATABASE_FILENAME, data)?;
+    let mut synced = synced.lock().unwrap();
+    if synced.notes == theirs {
+        return None;
     }
+    let base = std::mem::replace(&mut synced.notes, theirs.clone());
+    Some((base, theirs))
+}
+
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|meta| meta.modified())
+        .ok()
 }
 
 fn fuzzy_match(haystack: &str, needle: &str) -> bool {
@@ -636,6 +3935,22 @@ fn fuzzy_match(haystack: &str, needle: &str) -> bool {
 
 #[tokio::main]
 async fn main() -> eframe::Result<()> {
+    let mut args = Args::parse();
+    if let Err(e) = Settings::default().with_overrides(&args.settings_overrides()) {
+        eprintln!("cardamom-chai: {e}");
+        std::process::exit(2);
+    }
+    let command = args.command.take();
+    ARGS.set(args).ok();
+
+    if let Some(command) = command {
+        if let Err(e) = cli::run(command).await {
+            eprintln!("cardamom-chai: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     chai_tea::brew_async(
         "cardamom-chai",
         init,
@@ -645,3 +3960,273 @@ async fn main() -> eframe::Result<()> {
         run_cmd,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Carries out the storage commands among `cmds` on `store`, the way
+    /// `run_cmd` does on the profile's storage.
+    fn persist(store: &mut dyn Storage, cmds: &[Cmd]) {
+        for cmd in cmds {
+            match cmd {
+                Cmd::WriteTasks(tasks) => store.save_tasks(tasks).unwrap(),
+                Cmd::WriteNotes(notes) => store.save_notes(notes).unwrap(),
+                Cmd::AppendEvent(event) => store.append(event).unwrap(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Passes `msgs` through `update` one at a time, persisting the
+    /// commands of each to `store`.
+    fn run(store: &mut storage::Memory, m: Model, msgs: Vec<Msg>) -> Model {
+        msgs.into_iter().fold(m, |m, msg| {
+            let (m, cmds) = update(m, msg);
+            persist(store, &cmds);
+            m
+        })
+    }
+
+    fn writes(cmds: &[Cmd]) -> (usize, usize) {
+        let count = |f: fn(&Cmd) -> bool| cmds.iter().filter(|cmd| f(cmd)).count();
+        (
+            count(|cmd| matches!(cmd, Cmd::WriteTasks(_))),
+            count(|cmd| matches!(cmd, Cmd::WriteNotes(_))),
+        )
+    }
+
+    #[test]
+    fn edits_are_written_to_storage() {
+        let mut store = storage::Memory::default();
+        let msgs = vec![Msg::TextInput("buy tea!".to_string()), Msg::Add];
+        let m = run(&mut store, Model::default(), msgs);
+        let id = m.tasks[0].task_id;
+        let msgs = vec![
+            Msg::CheckBox(id, true),
+            Msg::EditNote,
+            Msg::EditNoteInput("steep for 3 minutes".to_string()),
+            Msg::EditNoteDone,
+        ];
+        let m = run(&mut store, m, msgs);
+
+        let stored = store.load_tasks().unwrap();
+        assert!(stored == m.tasks);
+        assert_eq!(stored[0].task_text, "buy tea");
+        assert!(stored[0].state == TaskState::Chosen);
+        assert!(stored[0].done);
+        assert_eq!(store.load_notes().unwrap(), "steep for 3 minutes");
+    }
+
+    #[test]
+    fn edits_write_only_what_they_change() {
+        let mut store = storage::Memory::default();
+        let msgs = vec![Msg::TextInput("a".to_string()), Msg::Add];
+        let m = run(&mut store, Model::default(), msgs);
+        let id = m.tasks[0].task_id;
+
+        let (m, cmds) = update(m, Msg::CycleTaskState(id));
+        assert_eq!(writes(&cmds), (1, 0));
+        let (m, cmds) = update(m, Msg::EditNoteInput("unsaved".to_string()));
+        assert_eq!(writes(&cmds), (0, 0));
+        let (_, cmds) = update(m, Msg::EditNoteDone);
+        assert_eq!(writes(&cmds), (0, 1));
+    }
+
+    #[test]
+    fn loading_writes_nothing_back() {
+        let mut store = storage::Memory::default();
+        store
+            .save_tasks(&[Task::new("a".to_string(), TaskState::Normal)])
+            .unwrap();
+        store.save_notes("notes").unwrap();
+
+        let loaded = Msg::LoadedTasks(store.load_tasks().unwrap());
+        let (m, cmds) = update(Model::default(), loaded);
+        assert!(cmds.is_empty());
+        let (m, cmds) = update(m, Msg::LoadedNotes(store.load_notes().unwrap()));
+        assert!(cmds.is_empty());
+        assert_eq!(m.tasks.len(), 1);
+        assert_eq!(m.notes, "notes");
+    }
+
+    #[test]
+    fn session_overrides_are_not_saved() {
+        let saved = Settings::default();
+        let overrides = vec![
+            "left_panel_width=300".to_string(),
+            "profile=work".to_string(),
+        ];
+        let loaded = Msg::LoadedSettings(saved.clone(), overrides);
+        let (m, _) = update(Model::default(), loaded);
+        let written = |cmds: Vec<Cmd>| {
+            cmds.into_iter().find_map(|cmd| match cmd {
+                Cmd::WriteSettings(settings) => Some(settings),
+                _ => None,
+            })
+        };
+
+        let (m, _) = update(m, Msg::OpenSettings);
+        let mut edit = m.settings_edit.clone().unwrap();
+        edit.auto_lock_minutes = 5;
+        let (m, _) = update(m, Msg::SettingsInput(edit));
+        let (m, cmds) = update(m, Msg::SaveSettings);
+        let expected = Settings {
+            auto_lock_minutes: 5,
+            ..saved.clone()
+        };
+        assert!(written(cmds) == Some(expected.clone()));
+        assert_eq!(m.settings.left_panel_width, 300.0);
+        assert_eq!(m.settings.auto_lock_minutes, 5);
+
+        let (m, cmds) = update(m, Msg::SetTheme("dark".to_string()));
+        let expected = Settings {
+            theme: "dark".to_string(),
+            ..expected
+        };
+        assert!(written(cmds) == Some(expected));
+        assert_eq!(m.settings.profile, "work");
+    }
+
+    #[test]
+    fn invalid_profile_names_open_the_default_profile() {
+        let saved = Settings {
+            profile: "../elsewhere".to_string(),
+            ..Settings::default()
+        };
+        let (m, cmds) = update(Model::default(), Msg::LoadedSettings(saved, vec![]));
+        let switched = cmds.iter().find_map(|cmd| match cmd {
+            Cmd::SwitchProfile(profile) => Some(profile.as_str()),
+            _ => None,
+        });
+        assert_eq!(switched, Some(profiles::DEFAULT_PROFILE));
+        assert_eq!(m.settings.profile, profiles::DEFAULT_PROFILE);
+        assert!(m.profile_status.is_some());
+    }
+
+    #[test]
+    fn requests_before_loading_are_refused() {
+        let mut store = storage::Memory::default();
+        store
+            .save_tasks(&[Task::new("a".to_string(), TaskState::Normal)])
+            .unwrap();
+        let add = |m| {
+            let request = ipc::Request::Add {
+                text: "b".to_string(),
+            };
+            update(m, Msg::IpcRequest(Uuid::new_v4(), request))
+        };
+
+        let (m, cmds) = add(init().0);
+        assert_eq!(writes(&cmds), (0, 0));
+        assert!(matches!(&cmds[..], [Cmd::IpcReply(_, response)] if !response.ok));
+
+        let msgs = vec![
+            Msg::LoadedTasks(store.load_tasks().unwrap()),
+            Msg::LoadedNotes(store.load_notes().unwrap()),
+        ];
+        let m = run(&mut store, m, msgs);
+        let (m, cmds) = add(m);
+        persist(&mut store, &cmds);
+        assert_eq!(m.tasks.len(), 2);
+        assert_eq!(store.load_tasks().unwrap().len(), 2);
+
+        let (m, _) = switch_profile(m, "work".to_string());
+        let (_, cmds) = add(m);
+        assert_eq!(writes(&cmds), (0, 0));
+    }
+
+    #[test]
+    fn imported_todo_txt_replaces_tasks_by_id() {
+        let mut store = storage::Memory::default();
+        let msgs = vec![Msg::TextInput("a".to_string()), Msg::Add, Msg::OpenTransfer];
+        let m = run(&mut store, Model::default(), msgs);
+        let id = m.tasks[0].task_id;
+        let file = format!("x 2026-01-02 a, done elsewhere id:{id}\nb\n");
+
+        let imported = table::parse_tasks(table::Format::TodoTxt, &file);
+        let msgs = vec![Msg::ImportedTasks("todo.txt".to_string(), imported)];
+        let m = run(&mut store, m, msgs);
+        let [a, b] = &m.tasks[..] else {
+            panic!("expected two tasks");
+        };
+        assert_eq!(a.task_id, id);
+        assert_eq!(a.task_text, "a, done elsewhere");
+        assert!(a.done);
+        assert_eq!(b.task_text, "b");
+        assert!(store.load_tasks().unwrap() == m.tasks);
+        let status = m.transfer.and_then(|dialog| dialog.status);
+        assert_eq!(status.as_deref(), Some("Imported 2 tasks from todo.txt."));
+    }
+
+    #[test]
+    fn reimported_taskwarrior_tasks_are_updated() {
+        let mut store = storage::Memory::default();
+        let msgs = vec![Msg::TextInput("a".to_string()), Msg::Add, Msg::OpenTransfer];
+        let m = run(&mut store, Model::default(), msgs);
+        let mut exported: Vec<serde_json::Value> =
+            serde_json::from_str(&taskwarrior::export(&m.tasks)).unwrap();
+        exported[0]["status"] = "completed".into();
+        exported[0]["end"] = "20300102T030405Z".into();
+        exported[0]["modified"] = "20300102T030405Z".into();
+
+        let json = serde_json::to_string(&exported).unwrap();
+        let imported = table::parse_tasks(table::Format::Taskwarrior, &json);
+        let msgs = vec![Msg::ImportedTasks("taskwarrior.json".to_string(), imported)];
+        let m = run(&mut store, m, msgs);
+        let [task] = &m.tasks[..] else {
+            panic!("expected the task to be updated in place");
+        };
+        assert!(task.done);
+        assert_eq!(task.modified.done.to_rfc3339(), "2030-01-02T03:04:05+00:00");
+    }
+
+    #[test]
+    fn saving_encrypted_notes_is_no_change_on_disk() {
+        let dir = std::env::temp_dir().join(format!("cardamom-chai-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = crypto::Key::derive("passphrase", [7; 16]).unwrap();
+        let mut store = crypto::Encrypted::new(&dir, key.clone());
+        let synced = Mutex::new(Synced::default());
+
+        // as `Cmd::WriteNotes` does
+        synced.lock().unwrap().notes = "secret".to_string();
+        store.save_notes("secret").unwrap();
+        let data = std::fs::read_to_string(dir.join(NOTES_DATABASE_FILENAME)).unwrap();
+        assert!(!data.contains("secret"));
+        assert!(notes_changed_on_disk(&synced, data, Some(&key)).is_none());
+
+        crypto::Encrypted::new(&dir, key.clone())
+            .save_notes("changed elsewhere")
+            .unwrap();
+        let data = std::fs::read_to_string(dir.join(NOTES_DATABASE_FILENAME)).unwrap();
+        let (base, theirs) = notes_changed_on_disk(&synced, data, Some(&key)).unwrap();
+        assert_eq!(base, "secret");
+        assert_eq!(theirs, "changed elsewhere");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn imports_are_journaled_and_written_once() {
+        let mut store = storage::Memory::default();
+        let msgs = vec![Msg::TextInput("a".to_string()), Msg::Add];
+        let m = run(&mut store, Model::default(), msgs);
+        let mut changed = m.tasks[0].clone();
+        changed.task_text = "a, changed".to_string();
+        changed.modified.text = Utc::now();
+        let imported = vec![changed, Task::new("b".to_string(), TaskState::Normal)];
+
+        let msg = Msg::ImportedJsonDatabase(imported, "imported notes".to_string());
+        let (m, cmds) = update(m, msg);
+        let events = cmds
+            .iter()
+            .filter(|cmd| matches!(cmd, Cmd::AppendEvent(_)))
+            .count();
+        assert_eq!(events, 3);
+        assert_eq!(writes(&cmds), (1, 1));
+
+        persist(&mut store, &cmds);
+        assert!(store.load_tasks().unwrap() == m.tasks);
+        assert_eq!(store.load_notes().unwrap(), "imported notes");
+    }
+}
@@ -0,0 +1,381 @@
+use crate::settings::Settings;
+use crate::storage::{self, Storage};
+use crate::{Task, TaskState};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const MARKDOWN_FILENAME: &str = "tasks.md";
+pub const NOTES_FILENAME: &str = "notes.md";
+
+/// Hides a task's id from rendered Markdown.
+const ID_COMMENT: (&str, &str) = ("<!-- id:", " -->");
+
+/// How an exported checklist is split into sections.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Grouping {
+    #[default]
+    State,
+    Tag,
+}
+
+impl Grouping {
+    pub const ALL: [Grouping; 2] = [Grouping::State, Grouping::Tag];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Grouping::State => "state",
+            Grouping::Tag => "tag",
+        }
+    }
+}
+
+/// Words such as `#tag`, `+project` or `@context` in a task's text.
+pub fn tags(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace()
+        .filter(|word| word.len() > 1 && word.starts_with(['#', '+', '@']))
+}
+
+/// A GitHub-flavored checklist item for `task`.
+pub fn item(task: &Task) -> String {
+    let check = if task.done { "x" } else { " " };
+    format!("- [{check}] {}", task.task_text.trim())
+}
+
+/// The tasks as a checklist with a `##` section per group, in the order
+/// they are shown.
+pub fn export(tasks: &[Task], grouping: Grouping) -> String {
+    let mut groups: Vec<(String, Vec<&Task>)> = vec![];
+    let mut add = |group: &str, task| match groups.iter_mut().find(|(name, _)| name == group) {
+        Some((_, tasks)) => tasks.push(task),
+        None => groups.push((group.to_string(), vec![task])),
+    };
+    for task in tasks.iter().rev() {
+        match grouping {
+            Grouping::State => add(state_heading(task.state), task),
+            Grouping::Tag => add(tags(&task.task_text).next().unwrap_or(""), task),
+        }
+    }
+    match grouping {
+        Grouping::State => groups.sort_by_key(|(name, _)| {
+            [TaskState::Chosen, TaskState::Uncertain, TaskState::Normal]
+                .iter()
+                .position(|state| state_heading(*state) == name)
+        }),
+        // untagged tasks go last
+        Grouping::Tag => groups.sort_by_key(|(name, _)| name.is_empty()),
+    }
+
+    let mut markdown = String::new();
+    for (name, tasks) in groups {
+        if !markdown.is_empty() {
+            markdown.push('\n');
+        }
+        let heading = if name.is_empty() { "Untagged" } else { &name };
+        markdown.push_str(&format!("## {heading}\n\n"));
+        for task in tasks {
+            markdown.push_str(&item(task));
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+fn state_heading(state: TaskState) -> &'static str {
+    match state {
+        TaskState::Chosen => "Active",
+        TaskState::Uncertain => "Uncertain",
+        TaskState::Normal => "Other",
+    }
+}
+
+/// A list item in a Markdown document.
+pub struct Item<'a> {
+    /// Indentation and bullet, e.g. `"  - "`.
+    pub prefix: &'a str,
+    /// Whether the item has a `[ ]` or `[x]` box.
+    pub checkbox: bool,
+    pub done: bool,
+    pub text: &'a str,
+}
+
+/// The list item on `line`, if it is one. Nested items are indented
+/// and parse the same.
+pub fn parse_item(line: &str) -> Option<Item<'_>> {
+    let rest = line.trim_start().strip_prefix(['-', '*', '+'])?;
+    let rest = rest.strip_prefix([' ', '\t'])?.trim_start();
+    let prefix = &line[..line.len() - rest.len()];
+
+    let (checkbox, done, text) = if let Some(text) = rest.strip_prefix("[ ]") {
+        (true, false, text)
+    } else if let Some(text) = rest.strip_prefix("[x]").or(rest.strip_prefix("[X]")) {
+        (true, true, text)
+    } else {
+        (false, false, rest)
+    };
+    let text = text.trim();
+    (!text.is_empty()).then_some(Item {
+        prefix,
+        checkbox,
+        done,
+        text,
+    })
+}
+
+/// New tasks for the list items in `markdown`, e.g. a pasted checklist,
+/// shown in the order they are listed. Tasks have no subtasks, so nested
+/// items become tasks of their own right after their parent.
+pub fn import(markdown: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = markdown
+        .lines()
+        .filter_map(parse_item)
+        .map(|item| {
+            let mut task = Task::new(item.text.to_string(), TaskState::Normal);
+            task.done = item.done;
+            task
+        })
+        .collect();
+    // the newest task is shown first
+    tasks.reverse();
+    tasks
+}
+
+/// A checklist line for `task` in a Markdown folder, with its state as a
+/// trailing `!` or `?` like in the conflict list, and its id in an HTML
+/// comment.
+fn task_line(prefix: &str, task: &Task) -> String {
+    let check = if task.done { "x" } else { " " };
+    let marker = match task.state {
+        TaskState::Normal => "",
+        TaskState::Chosen => " !",
+        TaskState::Uncertain => " ?",
+    };
+    let (open, close) = ID_COMMENT;
+    format!(
+        "{prefix}[{check}] {}{marker} {open}{}{close}",
+        task.task_text.trim(),
+        task.task_id
+    )
+}
+
+/// The task on a checklist line; the id is nil when the line has none.
+fn parse_task(line: &str) -> Option<Task> {
+    let item = parse_item(line).filter(|item| item.checkbox)?;
+    let mut text = item.text;
+    let mut task_id = Uuid::nil();
+    let (open, close) = ID_COMMENT;
+    if let Some((rest, comment)) = text.rsplit_once(open)
+        && let Some(id) = comment
+            .strip_suffix(close.trim_start())
+            .and_then(|id| Uuid::parse_str(id.trim()).ok())
+    {
+        text = rest.trim_end();
+        task_id = id;
+    }
+    let (text, state) = if let Some(text) = text.strip_suffix(" !") {
+        (text, TaskState::Chosen)
+    } else if let Some(text) = text.strip_suffix(" ?") {
+        (text, TaskState::Uncertain)
+    } else {
+        (text, TaskState::Normal)
+    };
+
+    let mut task = Task::new(text.trim_end().to_string(), state);
+    task.task_id = task_id;
+    task.done = item.done;
+    Some(task)
+}
+
+/// Each line of `markdown` with the task on it, if any.
+fn parse_lines(markdown: &str) -> Vec<(&str, Option<Task>)> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    markdown
+        .lines()
+        .map(|line| {
+            let task = parse_task(line).map(|mut task| {
+                if task.task_id.is_nil() {
+                    let occurrence = occurrences.entry(line.trim()).or_default();
+                    task.task_id = storage::line_id(line, *occurrence);
+                    *occurrence += 1;
+                }
+                task
+            });
+            (line, task)
+        })
+        .collect()
+}
+
+/// The tasks in a Markdown folder's `tasks.md`. The file lists them in
+/// the order they are shown, newest first.
+pub fn parse(markdown: &str, known: &[Task]) -> Vec<Task> {
+    let mut tasks: Vec<Task> = parse_lines(markdown)
+        .into_iter()
+        .filter_map(|(_, task)| task)
+        .map(|mut task| {
+            storage::carry_over(&mut task, known);
+            task
+        })
+        .collect();
+    tasks.reverse();
+    tasks
+}
+
+/// `tasks.md` and `notes.md` in a folder such as an Obsidian vault, both
+/// editable elsewhere. Checklist items are the tasks; headings, prose and
+/// plain list items around them are kept as they are.
+pub struct Folder {
+    tasks_path: PathBuf,
+    notes_path: PathBuf,
+    json: storage::Json,
+    /// The tasks as last loaded or saved.
+    known: Vec<Task>,
+}
+
+impl Folder {
+    /// Settings are kept as JSON in `dir`.
+    pub fn new(folder: &Path, dir: &Path) -> Folder {
+        Folder {
+            tasks_path: folder.join(MARKDOWN_FILENAME),
+            notes_path: folder.join(NOTES_FILENAME),
+            json: storage::Json::new(dir),
+            known: vec![],
+        }
+    }
+
+    fn write(path: &Path, old: &str, new: &str) -> Result<(), String> {
+        if old == new {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        std::fs::write(path, new).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl Storage for Folder {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        let markdown = storage::read_or_empty(&self.tasks_path)?;
+        self.known = parse(&markdown, &self.known);
+        Ok(self.known.clone())
+    }
+
+    /// Tasks are written on the checklist lines that hold them, so they
+    /// stay under their headings and keep their indentation. Lines of
+    /// deleted tasks are dropped and new tasks are added after the last
+    /// checklist item.
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        let old = storage::read_or_empty(&self.tasks_path)?;
+        let lines = parse_lines(&old);
+        let listed: HashSet<Uuid> = lines
+            .iter()
+            .filter_map(|(_, task)| task.as_ref().map(|task| task.task_id))
+            .collect();
+        let mut added = tasks
+            .iter()
+            .rev()
+            .filter(|task| !listed.contains(&task.task_id));
+
+        let last_item = lines.iter().rposition(|(_, task)| task.is_some());
+        let mut written = HashSet::new();
+        let mut markdown = String::new();
+        let mut push = |line: &str| {
+            markdown.push_str(line);
+            markdown.push('\n');
+        };
+        for (i, (line, original)) in lines.iter().enumerate() {
+            match original {
+                None => push(line),
+                Some(original) => {
+                    let task = tasks.iter().find(|task| task.task_id == original.task_id);
+                    // a line copied along with its id holds the task only once
+                    if let Some(task) = task.filter(|task| written.insert(task.task_id)) {
+                        let unchanged = storage::same_fields(original, task)
+                            && line.contains(&format!("{}{}", ID_COMMENT.0, task.task_id));
+                        if unchanged {
+                            push(line);
+                        } else {
+                            let prefix = parse_item(line).map(|item| item.prefix).unwrap_or("- ");
+                            push(&task_line(prefix, task));
+                        }
+                    }
+                }
+            }
+            if Some(i) == last_item {
+                for task in added.by_ref() {
+                    push(&task_line("- ", task));
+                }
+            }
+        }
+        for task in added {
+            push(&task_line("- ", task));
+        }
+
+        Folder::write(&self.tasks_path, &old, &markdown)?;
+        self.known = tasks.to_vec();
+        Ok(())
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        storage::read_or_empty(&self.notes_path)
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        let old = storage::read_or_empty(&self.notes_path)?;
+        Folder::write(&self.notes_path, &old, notes)
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        self.json.load_settings()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        self.json.save_settings(settings)
+    }
+
+    /// The folder is shared with other tools, so what is there wins.
+    fn keeps_existing(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_keeps_tasks_on_their_lines() {
+        let dir = std::env::temp_dir().join(format!("cardamom-chai-{}", Uuid::new_v4()));
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let original = format!(
+            "# Plans\n\n## Work\n\n- [ ] write the report ! <!-- id:{} -->\n\
+             - [ ] call the bank <!-- id:{} -->\n\n## Home\n\n\
+             - [x] water the plants <!-- id:{} -->\n\
+             \x20 - [ ] fix the sink <!-- id:{} -->\n\nSome prose.\n",
+            ids[0], ids[1], ids[2], ids[3]
+        );
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(MARKDOWN_FILENAME), &original).unwrap();
+
+        let mut folder = Folder::new(&dir, &dir);
+        let mut tasks = folder.load_tasks().unwrap();
+        tasks.retain(|task| task.task_id != ids[1]);
+        let new = Task::new("buy bread".to_string(), TaskState::Normal);
+        tasks.push(new.clone());
+        folder.save_tasks(&tasks).unwrap();
+
+        let saved = std::fs::read_to_string(dir.join(MARKDOWN_FILENAME)).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        let expected: Vec<String> = original
+            .lines()
+            .filter(|line| !line.contains(&ids[1].to_string()))
+            .map(str::to_string)
+            .flat_map(|line| {
+                let after = line.contains(&ids[3].to_string());
+                let new = after.then(|| task_line("- ", &new));
+                std::iter::once(line).chain(new)
+            })
+            .collect();
+        assert_eq!(saved.lines().collect::<Vec<_>>(), expected);
+    }
+}
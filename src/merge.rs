@@ -0,0 +1,107 @@
+use crate::Task;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Side {
+    Mine,
+    Theirs,
+    Both,
+}
+
+/// A task changed differently in memory and on disk. `None` means deleted.
+#[derive(Clone)]
+pub struct TaskConflict {
+    pub task_id: Uuid,
+    pub mine: Option<Task>,
+    pub theirs: Option<Task>,
+}
+
+pub struct Merged {
+    pub tasks: Vec<Task>,
+    pub conflicts: Vec<TaskConflict>,
+}
+
+fn find(tasks: &[Task], id: Uuid) -> Option<&Task> {
+    tasks.iter().find(|t| t.task_id == id)
+}
+
+/// Three-way merge of task lists by `task_id`, where `base` is the version
+/// both sides started from. A task changed on only one side takes that
+/// side's version; a task changed on both sides is a conflict and keeps
+/// `mine` until resolved. Order follows `theirs`, with tasks only present
+/// in `mine` appended in their original order.
+pub fn merge_tasks(base: &[Task], mine: &[Task], theirs: &[Task]) -> Merged {
+    let mut tasks = vec![];
+    let mut conflicts = vec![];
+
+    let ids = theirs
+        .iter()
+        .chain(mine.iter())
+        .chain(base.iter())
+        .map(|t| t.task_id);
+
+    let mut seen = vec![];
+    for id in ids {
+        if seen.contains(&id) {
+            continue;
+        }
+        seen.push(id);
+
+        let b = find(base, id);
+        let m = find(mine, id);
+        let t = find(theirs, id);
+
+        let merged = if m == t || m == b {
+            t
+        } else if t == b {
+            m
+        } else {
+            conflicts.push(TaskConflict {
+                task_id: id,
+                mine: m.cloned(),
+                theirs: t.cloned(),
+            });
+            m
+        };
+
+        if let Some(task) = merged {
+            tasks.push(task.clone());
+        }
+    }
+
+    Merged { tasks, conflicts }
+}
+
+/// Applies a resolved conflict to `tasks`, keeping the task's position when
+/// it is still present.
+pub fn resolve(tasks: &mut Vec<Task>, conflict: &TaskConflict, side: Side) {
+    let chosen = match side {
+        Side::Mine => &conflict.mine,
+        Side::Theirs | Side::Both => &conflict.theirs,
+    };
+
+    let position = tasks.iter().position(|t| t.task_id == conflict.task_id);
+    match (position, chosen) {
+        (Some(i), Some(task)) => tasks[i] = task.clone(),
+        (Some(i), None) => {
+            tasks.remove(i);
+        }
+        (None, Some(task)) => tasks.push(task.clone()),
+        (None, None) => {}
+    }
+
+    if side == Side::Both
+        && let Some(mine) = &conflict.mine
+        && conflict.theirs.is_some()
+    {
+        // keep my version as a separate task next to theirs
+        tasks.push(Task {
+            task_id: Uuid::new_v4(),
+            ..mine.clone()
+        });
+    }
+}
+
+pub fn merge_notes(mine: &str, theirs: &str) -> String {
+    format!("{mine}\n\n---\n\n{theirs}")
+}
@@ -0,0 +1,223 @@
+use crate::markdown;
+use crate::{Task, TaskState};
+use uuid::Uuid;
+
+fn is_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '%'))
+}
+
+/// `#tag` words become `tag`, while `@context` stays as it is, since org
+/// tags may start with `@`.
+fn org_tag(word: &str) -> Option<&str> {
+    let tag = word.strip_prefix('#').unwrap_or(word);
+    is_tag(tag).then_some(tag)
+}
+
+/// The `+project` words of `task`, which are kept in its `:PROJECT:`
+/// property rather than among its tags.
+fn projects(task: &Task) -> impl Iterator<Item = &str> {
+    task.task_text
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('+'))
+        .filter(|project| is_tag(project))
+}
+
+fn headline(task: &Task) -> String {
+    let mut words = vec![];
+    let mut tags = vec![];
+    for word in task.task_text.split_whitespace() {
+        if word.strip_prefix('+').is_some_and(is_tag) {
+            continue;
+        }
+        match markdown::tags(word).next().and_then(org_tag) {
+            Some(tag) => tags.push(tag),
+            None => words.push(word),
+        }
+    }
+
+    let keyword = if task.done { "DONE" } else { "TODO" };
+    let cookie = match task.state {
+        TaskState::Chosen => " [#A]",
+        TaskState::Uncertain => " [#C]",
+        TaskState::Normal => "",
+    };
+    let mut line = format!("** {keyword}{cookie} {}", words.join(" "));
+    if !tags.is_empty() {
+        line.push_str(&format!(" :{}:", tags.join(":")));
+    }
+    line
+}
+
+/// An org document with a `TODO`/`DONE` headline per task under `* Tasks`,
+/// followed by the notes under `* Notes`.
+pub fn export(tasks: &[Task], notes: &str) -> String {
+    let mut org = String::from("#+TITLE: cardamom-chai\n\n* Tasks\n");
+    for task in tasks {
+        org.push_str(&headline(task));
+        org.push('\n');
+        if task.done {
+            let closed = task.modified.done.format("%Y-%m-%d %a %H:%M");
+            org.push_str(&format!("   CLOSED: [{closed}]\n"));
+        }
+        org.push_str(&format!("   :PROPERTIES:\n   :ID: {}\n", task.task_id));
+        let projects: Vec<&str> = projects(task).collect();
+        if !projects.is_empty() {
+            org.push_str(&format!("   :PROJECT: {}\n", projects.join(" ")));
+        }
+        org.push_str("   :END:\n");
+    }
+
+    if !notes.is_empty() {
+        // the notes are Markdown, kept verbatim in a block
+        org.push_str("\n* Notes\n#+BEGIN_SRC markdown\n");
+        for line in notes.lines() {
+            if line.starts_with('*') || line.starts_with("#+") {
+                org.push(',');
+            }
+            org.push_str(line);
+            org.push('\n');
+        }
+        org.push_str("#+END_SRC\n");
+    }
+    org
+}
+
+/// The task on a headline with a `TODO` or `DONE` keyword.
+fn parse_headline(line: &str) -> Option<Task> {
+    let rest = line.strip_prefix('*')?.trim_start_matches('*');
+    let rest = rest.strip_prefix(' ')?.trim_start();
+    let (done, rest) = if let Some(rest) = rest.strip_prefix("TODO") {
+        (false, rest)
+    } else if let Some(rest) = rest.strip_prefix("DONE") {
+        (true, rest)
+    } else {
+        return None;
+    };
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    let mut rest = rest.trim();
+
+    let mut state = TaskState::Normal;
+    if let Some(after) = rest.strip_prefix("[#") {
+        let mut chars = after.chars();
+        if let (Some(priority), Some(']')) = (chars.next(), chars.next()) {
+            state = match priority {
+                'A' | 'B' => TaskState::Chosen,
+                _ => TaskState::Uncertain,
+            };
+            rest = chars.as_str().trim_start();
+        }
+    }
+
+    // trailing `:tag:tag:`
+    let mut words: Vec<String> = rest.split_whitespace().map(str::to_string).collect();
+    if let Some(last) = words.last()
+        && last.len() > 2
+        && last.starts_with(':')
+        && last.ends_with(':')
+        && last.trim_matches(':').split(':').all(is_tag)
+    {
+        let tags = words.pop().unwrap_or_default();
+        words.extend(tags.trim_matches(':').split(':').map(|tag| {
+            if tag.starts_with(['@', '#']) {
+                tag.to_string()
+            } else {
+                format!("#{tag}")
+            }
+        }));
+    }
+
+    let mut task = Task::new(words.join(" "), state);
+    task.done = done;
+    Some(task)
+}
+
+/// Tasks for the `TODO`/`DONE` headlines and the checkbox items in an org
+/// document. Priority `[#A]` or `[#B]` makes a task chosen and a lower one
+/// uncertain; org tags become `#tag` words and the `:PROJECT:` property
+/// `+project` words. Headlines keep the id in their `:ID:` property.
+pub fn import(org: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+    // the last headline, which an `:ID:` property applies to
+    let mut headline: Option<usize> = None;
+    let mut in_block = false;
+    for line in org.lines() {
+        let trimmed = line.trim();
+        let upper = trimmed.to_uppercase();
+        if upper.starts_with("#+BEGIN_") {
+            in_block = true;
+        } else if upper.starts_with("#+END_") {
+            in_block = false;
+        }
+        if in_block {
+            continue;
+        }
+
+        if line.starts_with('*') {
+            headline = None;
+            if let Some(task) = parse_headline(line) {
+                headline = Some(tasks.len());
+                tasks.push(task);
+            }
+        } else if let Some(id) = trimmed.strip_prefix(":ID:") {
+            if let (Some(i), Ok(id)) = (headline, Uuid::parse_str(id.trim())) {
+                tasks[i].task_id = id;
+            }
+        } else if let Some(projects) = trimmed.strip_prefix(":PROJECT:") {
+            if let Some(i) = headline {
+                let text = &mut tasks[i].task_text;
+                for project in projects.split_whitespace() {
+                    text.push_str(&format!(" +{project}"));
+                }
+            }
+        } else if let Some(item) = markdown::parse_item(line).filter(|item| item.checkbox) {
+            let mut task = Task::new(item.text.to_string(), TaskState::Normal);
+            task.done = item.done;
+            tasks.push(task);
+        }
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(tasks: &[Task]) -> Vec<Task> {
+        import(&export(tasks, "# notes\n* not a headline"))
+    }
+
+    #[test]
+    fn tags_projects_and_contexts_survive_export_and_import() {
+        let mut task = Task::new(
+            "call the plumber @phone #urgent +house +garden".to_string(),
+            TaskState::Chosen,
+        );
+        task.done = true;
+
+        let imported = round_trip(std::slice::from_ref(&task));
+        let [imported] = &imported[..] else {
+            panic!("expected one task");
+        };
+        assert_eq!(imported.task_id, task.task_id);
+        assert_eq!(imported.task_text, task.task_text);
+        assert!(imported.done && imported.state == TaskState::Chosen);
+    }
+
+    #[test]
+    fn projects_are_not_read_back_as_tags() {
+        let tasks = [
+            Task::new("water the plants +home".to_string(), TaskState::Normal),
+            Task::new("water the plants #home".to_string(), TaskState::Uncertain),
+        ];
+        let texts: Vec<String> = round_trip(&tasks)
+            .into_iter()
+            .map(|task| task.task_text)
+            .collect();
+        assert_eq!(texts, ["water the plants +home", "water the plants #home"]);
+    }
+}
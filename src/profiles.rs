@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The default profile lives directly in the data dir so existing databases
+/// keep working; named profiles live under `profiles/<name>`.
+pub fn profile_dir(base: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT_PROFILE {
+        base.to_path_buf()
+    } else {
+        base.join("profiles").join(name)
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ' '))
+}
+
+pub fn list(base: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(base.join("profiles"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| is_valid_name(name) && name != DEFAULT_PROFILE)
+        .collect();
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+    names
+}
@@ -0,0 +1,321 @@
+use crate::{git, profiles};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const SETTINGS_FILENAME: &str = "settings.json";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub profile: String,
+    pub storage: Backend,
+    /// Absolute path of the `todo.txt` used by todo.txt storage; empty uses
+    /// one in the profile directory
+    pub todo_txt_path: String,
+    /// Absolute path of the folder holding `tasks.md` and `notes.md` for
+    /// markdown storage; empty uses the profile directory
+    pub markdown_dir: String,
+    /// Commit the data dir to git after changes settle
+    pub git_history: bool,
+    /// Local path or `file://` URL of a bare repository to pull from and push to
+    pub git_remote: String,
+    /// Encrypt `database.json` and `notes-database.json` with a passphrase
+    pub encryption: bool,
+    /// Lock again after this many idle minutes; 0 never locks
+    pub auto_lock_minutes: u32,
+    /// Serve the REST API on localhost, authorized by the token in `api-token`
+    pub http_api: bool,
+    pub http_port: u16,
+    pub theme: String,
+    pub left_panel_width: f32,
+    pub right_panel_width: f32,
+    pub hotkeys: Hotkeys,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// `database.json` and `notes-database.json` snapshots
+    #[default]
+    Json,
+    /// Per-device CRDT replicas under `crdt/`, merged on load
+    Crdt,
+    /// Append-only `journal.jsonl` of events plus periodic snapshots
+    Journal,
+    /// `database.sqlite`, updated row by row
+    Sqlite,
+    /// A hand-editable `todo.txt` and `done.txt`
+    TodoTxt,
+    /// `tasks.md` and `notes.md` in a folder such as an Obsidian vault
+    Markdown,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 6] = [
+        Backend::Json,
+        Backend::Crdt,
+        Backend::Journal,
+        Backend::Sqlite,
+        Backend::TodoTxt,
+        Backend::Markdown,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Crdt => "crdt",
+            Backend::Journal => "journal",
+            Backend::Sqlite => "sqlite",
+            Backend::TodoTxt => "todo.txt",
+            Backend::Markdown => "markdown",
+        }
+    }
+}
+
+/// Key names as understood by `egui::Key::from_name`, e.g. `"A"` or `"Slash"`.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hotkeys {
+    pub focus_add: String,
+    pub search: String,
+    pub filter_all: String,
+    pub filter_active: String,
+    pub filter_pending: String,
+    pub filter_uncertain: String,
+    pub filter_done: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            profile: profiles::DEFAULT_PROFILE.to_string(),
+            storage: Backend::default(),
+            todo_txt_path: String::new(),
+            markdown_dir: String::new(),
+            git_history: false,
+            git_remote: String::new(),
+            encryption: false,
+            auto_lock_minutes: 10,
+            http_api: false,
+            http_port: 7837,
+            theme: "light".to_string(),
+            left_panel_width: 350.0,
+            right_panel_width: 300.0,
+            hotkeys: Hotkeys::default(),
+        }
+    }
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys {
+            focus_add: "Enter".to_string(),
+            search: "Slash".to_string(),
+            filter_all: "A".to_string(),
+            filter_active: "F".to_string(),
+            filter_pending: "P".to_string(),
+            filter_uncertain: "U".to_string(),
+            filter_done: "D".to_string(),
+        }
+    }
+}
+
+impl Hotkeys {
+    pub fn bindings(&self) -> [(&'static str, &str); 7] {
+        [
+            ("focus_add", &self.focus_add),
+            ("search", &self.search),
+            ("filter_all", &self.filter_all),
+            ("filter_active", &self.filter_active),
+            ("filter_pending", &self.filter_pending),
+            ("filter_uncertain", &self.filter_uncertain),
+            ("filter_done", &self.filter_done),
+        ]
+    }
+
+    pub fn bindings_mut(&mut self) -> [(&'static str, &mut String); 7] {
+        [
+            ("focus_add", &mut self.focus_add),
+            ("search", &mut self.search),
+            ("filter_all", &mut self.filter_all),
+            ("filter_active", &mut self.filter_active),
+            ("filter_pending", &mut self.filter_pending),
+            ("filter_uncertain", &mut self.filter_uncertain),
+            ("filter_done", &mut self.filter_done),
+        ]
+    }
+}
+
+/// Returns whether the hotkey named `name` was pressed this frame.
+/// Unknown key names never fire.
+pub fn pressed(ui: &egui::Ui, name: &str) -> bool {
+    match egui::Key::from_name(name) {
+        Some(key) => ui.input(|i| i.key_pressed(key)),
+        None => false,
+    }
+}
+
+impl Settings {
+    /// Human readable problems with these settings; empty when they are valid.
+    pub fn validate(&self, themes: &[String]) -> Vec<String> {
+        let mut errors = vec![];
+
+        if !profiles::is_valid_name(&self.profile) {
+            errors.push(format!("invalid profile name '{}'", self.profile));
+        }
+
+        if !self.git_remote.is_empty() && !git::is_local_remote(&self.git_remote) {
+            errors.push("git remote must be an absolute path or a file:// URL".to_string());
+        }
+
+        for (name, path) in [
+            ("todo_txt_path", &self.todo_txt_path),
+            ("markdown_dir", &self.markdown_dir),
+        ] {
+            if !path.is_empty() && !std::path::Path::new(path).is_absolute() {
+                errors.push(format!("{name} must be an absolute path"));
+            }
+        }
+
+        if self.encryption && self.storage != Backend::Json {
+            errors.push("encryption is only available with json storage".to_string());
+        }
+
+        if self.auto_lock_minutes > 24 * 60 {
+            errors.push("auto_lock_minutes must be at most 1440".to_string());
+        }
+
+        if self.http_port < 1024 {
+            errors.push("http_port must be at least 1024".to_string());
+        }
+
+        if !themes.is_empty() && !themes.contains(&self.theme) {
+            errors.push(format!("unknown theme '{}'", self.theme));
+        }
+
+        for (name, width) in [
+            ("left_panel_width", self.left_panel_width),
+            ("right_panel_width", self.right_panel_width),
+        ] {
+            if !(80.0..=1000.0).contains(&width) {
+                errors.push(format!("{name} must be between 80 and 1000"));
+            }
+        }
+
+        let bindings = self.hotkeys.bindings();
+        for (i, (name, key)) in bindings.iter().enumerate() {
+            match egui::Key::from_name(key) {
+                None => errors.push(format!("hotkey {name}: unknown key '{key}'")),
+                Some(k) => {
+                    if let Some((other, _)) = bindings[..i]
+                        .iter()
+                        .find(|(_, o)| egui::Key::from_name(o) == Some(k))
+                    {
+                        errors.push(format!("hotkey {name}: '{key}' is already used by {other}"));
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Applies `key=value` overrides, where `key` is a dotted path such as
+    /// `hotkeys.search`. Values are read as JSON, falling back to a string.
+    /// Overrides that make the settings invalid are an error.
+    pub fn with_overrides(self, overrides: &[String]) -> Result<Settings, String> {
+        let mut value = serde_json::to_value(&self).map_err(|e| e.to_string())?;
+
+        for item in overrides {
+            let (key, raw) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got '{item}'"))?;
+            let new_value = serde_json::from_str(raw)
+                .unwrap_or_else(|_| serde_json::Value::String(raw.to_string()));
+
+            let mut target = &mut value;
+            for part in key.split('.') {
+                target = target
+                    .get_mut(part)
+                    .ok_or_else(|| format!("unknown setting '{key}'"))?;
+            }
+            *target = new_value;
+        }
+
+        let settings: Settings = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        // problems the settings already had are not the overrides' doing
+        let existing = self.validate(&[]);
+        let errors: Vec<String> = settings
+            .validate(&[])
+            .into_iter()
+            .filter(|e| !existing.contains(e))
+            .collect();
+        if errors.is_empty() {
+            Ok(settings)
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// These settings with the fields that differ between `before` and
+    /// `after` set as in `after`, so an edit made to the session's settings
+    /// can be saved without the session's overrides.
+    pub fn with_changes(&self, before: &Settings, after: &Settings) -> Settings {
+        fn apply(target: &mut Value, before: &Value, after: &Value) {
+            if before == after {
+                return;
+            }
+            match (target, before, after) {
+                (Value::Object(target), Value::Object(before), Value::Object(after)) => {
+                    for (key, after) in after {
+                        if let Some(target) = target.get_mut(key)
+                            && let Some(before) = before.get(key)
+                        {
+                            apply(target, before, after);
+                        }
+                    }
+                }
+                (target, _, after) => *target = after.clone(),
+            }
+        }
+
+        let to_value = |settings| serde_json::to_value(settings).expect("failed to serialize");
+        let mut value = to_value(self);
+        apply(&mut value, &to_value(before), &to_value(after));
+        serde_json::from_value(value).unwrap_or_else(|_| after.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overridden(set: &str) -> Result<Settings, String> {
+        Settings::default().with_overrides(&[set.to_string()])
+    }
+
+    #[test]
+    fn overrides_are_validated() {
+        assert!(overridden("http_port=8080").is_ok_and(|s| s.http_port == 8080));
+        assert_eq!(
+            overridden("http_port=80").err().as_deref(),
+            Some("http_port must be at least 1024")
+        );
+        assert!(overridden("profile=\"\"").is_err());
+        assert!(overridden("profile=../elsewhere").is_err());
+        assert!(overridden("hotkeys.search=NoSuchKey").is_err());
+    }
+
+    #[test]
+    fn saved_settings_problems_do_not_block_overrides() {
+        let saved = Settings {
+            left_panel_width: 5.0,
+            ..Settings::default()
+        };
+        let settings = saved
+            .with_overrides(&["http_port=8080".to_string()])
+            .unwrap();
+        assert_eq!(settings.http_port, 8080);
+    }
+}
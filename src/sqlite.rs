@@ -0,0 +1,269 @@
+use crate::settings::Settings;
+use crate::storage::Storage;
+use crate::{Filter, Modified, Task, TaskState};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+pub const SQLITE_FILENAME: &str = "database.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tasks (
+    task_id TEXT PRIMARY KEY,
+    position INTEGER NOT NULL,
+    task_text TEXT NOT NULL,
+    done INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    text_modified TEXT NOT NULL,
+    done_modified TEXT NOT NULL,
+    state_modified TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tasks_position ON tasks (position);
+CREATE INDEX IF NOT EXISTS tasks_done ON tasks (done);
+CREATE INDEX IF NOT EXISTS tasks_state ON tasks (state);
+CREATE TABLE IF NOT EXISTS notes (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    notes TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    settings TEXT NOT NULL
+);
+";
+
+/// Tasks and notes in a SQLite database. Saves only write the rows that
+/// changed since the last load or save.
+pub struct Sqlite {
+    conn: Connection,
+    /// Position and contents of every task as stored.
+    saved: HashMap<Uuid, (i64, Task)>,
+    saved_notes: Option<String>,
+}
+
+fn state_name(state: TaskState) -> &'static str {
+    match state {
+        TaskState::Normal => "Normal",
+        TaskState::Chosen => "Chosen",
+        TaskState::Uncertain => "Uncertain",
+    }
+}
+
+fn parse_state(name: &str) -> TaskState {
+    match name {
+        "Chosen" => TaskState::Chosen,
+        "Uncertain" => TaskState::Uncertain,
+        _ => TaskState::Normal,
+    }
+}
+
+fn parse_time(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+/// A LIKE pattern matching the same subsequences as `fuzzy_match`.
+fn like_pattern(search: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in search.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+        pattern.push('%');
+    }
+    pattern
+}
+
+impl Sqlite {
+    pub fn open(path: &Path) -> Result<Sqlite, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        let mut sqlite = Sqlite {
+            conn,
+            saved: HashMap::new(),
+            saved_notes: None,
+        };
+        sqlite.load_tasks()?;
+        Ok(sqlite)
+    }
+
+    fn read_tasks(&self) -> rusqlite::Result<Vec<(i64, Task)>> {
+        let mut statement = self.conn.prepare_cached(
+            "SELECT position, task_id, task_text, done, state,
+                    text_modified, done_modified, state_modified
+             FROM tasks ORDER BY position",
+        )?;
+        let rows = statement.query_map([], |row| {
+            let task_id: String = row.get(1)?;
+            let state: String = row.get(4)?;
+            let text_modified: String = row.get(5)?;
+            let done_modified: String = row.get(6)?;
+            let state_modified: String = row.get(7)?;
+            Ok((
+                row.get(0)?,
+                Task {
+                    task_id: Uuid::parse_str(&task_id).unwrap_or_default(),
+                    task_text: row.get(2)?,
+                    done: row.get(3)?,
+                    state: parse_state(&state),
+                    modified: Modified {
+                        text: parse_time(&text_modified),
+                        done: parse_time(&done_modified),
+                        state: parse_time(&state_modified),
+                    },
+                },
+            ))
+        })?;
+        rows.collect()
+    }
+}
+
+impl Storage for Sqlite {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        let rows = self.read_tasks().map_err(|e| e.to_string())?;
+        self.saved = rows
+            .iter()
+            .map(|(position, task)| (task.task_id, (*position, task.clone())))
+            .collect();
+        Ok(rows.into_iter().map(|(_, task)| task).collect())
+    }
+
+    /// Tasks keep their stored position as long as it still sorts after
+    /// the task before them, so appends and deletes touch a single row.
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        let mut saved = HashMap::with_capacity(tasks.len());
+        let transaction = self.conn.transaction().map_err(|e| e.to_string())?;
+        {
+            let mut upsert = transaction
+                .prepare_cached(
+                    "INSERT INTO tasks (task_id, position, task_text, done, state,
+                                        text_modified, done_modified, state_modified)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                     ON CONFLICT (task_id) DO UPDATE SET
+                         position = excluded.position,
+                         task_text = excluded.task_text,
+                         done = excluded.done,
+                         state = excluded.state,
+                         text_modified = excluded.text_modified,
+                         done_modified = excluded.done_modified,
+                         state_modified = excluded.state_modified",
+                )
+                .map_err(|e| e.to_string())?;
+
+            let mut previous = -1;
+            for task in tasks {
+                let old = self.saved.get(&task.task_id);
+                let position = match old {
+                    Some((position, _)) if *position > previous => *position,
+                    _ => previous + 1,
+                };
+                if !matches!(old, Some((p, t)) if *p == position && t == task) {
+                    upsert
+                        .execute(params![
+                            task.task_id.to_string(),
+                            position,
+                            task.task_text,
+                            task.done,
+                            state_name(task.state),
+                            task.modified.text.to_rfc3339(),
+                            task.modified.done.to_rfc3339(),
+                            task.modified.state.to_rfc3339(),
+                        ])
+                        .map_err(|e| e.to_string())?;
+                }
+                saved.insert(task.task_id, (position, task.clone()));
+                previous = position;
+            }
+
+            let mut delete = transaction
+                .prepare_cached("DELETE FROM tasks WHERE task_id = ?1")
+                .map_err(|e| e.to_string())?;
+            for id in self.saved.keys().filter(|id| !saved.contains_key(*id)) {
+                delete
+                    .execute([id.to_string()])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        transaction.commit().map_err(|e| e.to_string())?;
+        self.saved = saved;
+        Ok(())
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        let notes: Option<String> = self
+            .conn
+            .query_row("SELECT notes FROM notes WHERE id = 0", [], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let notes = notes.unwrap_or_default();
+        self.saved_notes = Some(notes.clone());
+        Ok(notes)
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        if self.saved_notes.as_deref() == Some(notes) {
+            return Ok(());
+        }
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO notes (id, notes) VALUES (0, ?1)",
+                [notes],
+            )
+            .map_err(|e| e.to_string())?;
+        self.saved_notes = Some(notes.to_string());
+        Ok(())
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        let settings: Option<String> = self
+            .conn
+            .query_row("SELECT settings FROM settings WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        settings
+            .map(|settings| serde_json::from_str(&settings).map_err(|e| e.to_string()))
+            .transpose()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        let json = serde_json::to_string(settings).expect("failed to serialize");
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO settings (id, settings) VALUES (0, ?1)",
+                [json],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn query(&mut self, filter: Filter, search: &str) -> Option<Vec<Uuid>> {
+        let condition = match filter {
+            Filter::All => return None,
+            Filter::Active => "state = 'Chosen'",
+            Filter::Uncertain => "state = 'Uncertain'",
+            Filter::Pending => "done = 0",
+            Filter::Done => "done = 1",
+            Filter::Search if search.is_empty() => return Some(vec![]),
+            Filter::Search => "task_text LIKE ?1 ESCAPE '\\'",
+        };
+        let pattern = (filter == Filter::Search).then(|| like_pattern(search));
+
+        let mut statement = self
+            .conn
+            .prepare_cached(&format!("SELECT task_id FROM tasks WHERE {condition}"))
+            .ok()?;
+        let ids = statement
+            .query_map(rusqlite::params_from_iter(pattern), |row| {
+                row.get::<_, String>(0)
+            })
+            .ok()?
+            .filter_map(|id| Uuid::parse_str(&id.ok()?).ok())
+            .collect();
+        Some(ids)
+    }
+}
@@ -0,0 +1,185 @@
+use crate::journal::Event;
+use crate::settings::{SETTINGS_FILENAME, Settings};
+use crate::{Filter, NOTES_DATABASE_FILENAME, TASK_DATABASE_FILENAME, Task};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Where tasks, notes and settings are persisted.
+pub trait Storage: Send {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String>;
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String>;
+    fn load_notes(&mut self) -> Result<String, String>;
+    fn save_notes(&mut self, notes: &str) -> Result<(), String>;
+    /// `None` when no settings have been saved yet.
+    fn load_settings(&mut self) -> Result<Option<Settings>, String>;
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String>;
+
+    /// Records a single change ahead of the next save, for backends that
+    /// keep a log of changes.
+    fn append(&mut self, _event: &Event) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Whether data already stored here wins over data migrated in from
+    /// another backend, e.g. because other devices contributed to it.
+    fn keeps_existing(&self) -> bool {
+        false
+    }
+
+    /// Ids of the tasks shown for `filter`, for backends that can answer
+    /// this without scanning every task. `search` is the search text
+    /// without its leading '/'.
+    fn query(&mut self, _filter: Filter, _search: &str) -> Option<Vec<Uuid>> {
+        None
+    }
+}
+
+pub fn read_settings(path: &Path) -> Result<Option<Settings>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|e| format!("{}: {e}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+pub fn write_settings(path: &Path, settings: &Settings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).expect("failed to serialize");
+    std::fs::write(path, json).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// The contents of `path`, or nothing when it does not exist yet.
+pub fn read_or_empty(path: &Path) -> Result<String, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+/// An id for a line of a hand-edited file that carries none, which stays
+/// the same for as long as the line does. `occurrence` tells identical
+/// lines apart.
+pub fn line_id(line: &str, occurrence: usize) -> Uuid {
+    let name = format!("{occurrence} {}", line.trim());
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes())
+}
+
+/// Whether the fields a line of a hand-edited file holds are the same.
+pub fn same_fields(a: &Task, b: &Task) -> bool {
+    a.task_text == b.task_text && a.done == b.done && a.state == b.state
+}
+
+/// Copies change times from `known` for the fields of a task read back
+/// from a file that did not change since, so only edits made to the file
+/// look new.
+pub fn carry_over(task: &mut Task, known: &[Task]) {
+    let Some(old) = known.iter().find(|t| t.task_id == task.task_id) else {
+        return;
+    };
+    if old.task_text == task.task_text {
+        task.modified.text = old.modified.text;
+    }
+    if old.done == task.done {
+        task.modified.done = old.modified.done;
+    }
+    if old.state == task.state {
+        task.modified.state = old.modified.state;
+    }
+}
+
+/// `database.json`, `notes-database.json` and `settings.json` in one
+/// directory. The default backend.
+pub struct Json {
+    dir: PathBuf,
+}
+
+impl Json {
+    pub fn new(dir: &Path) -> Json {
+        Json {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    fn read(&self, filename: &str) -> Result<Option<String>, String> {
+        let path = self.dir.join(filename);
+        match std::fs::read_to_string(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    fn write(&self, filename: &str, data: &str) -> Result<(), String> {
+        let path = self.dir.join(filename);
+        std::fs::write(&path, data).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+impl Storage for Json {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        match self.read(TASK_DATABASE_FILENAME)? {
+            Some(data) => serde_json::from_str(&data).map_err(|e| e.to_string()),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(tasks).expect("failed to serialize");
+        self.write(TASK_DATABASE_FILENAME, &json)
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        Ok(self.read(NOTES_DATABASE_FILENAME)?.unwrap_or_default())
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        self.write(NOTES_DATABASE_FILENAME, notes)
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        read_settings(&self.dir.join(SETTINGS_FILENAME))
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        write_settings(&self.dir.join(SETTINGS_FILENAME), settings)
+    }
+}
+
+/// Keeps everything in memory and writes nothing to disk.
+#[derive(Default)]
+pub struct Memory {
+    tasks: Vec<Task>,
+    notes: String,
+    settings: Option<Settings>,
+}
+
+impl Storage for Memory {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        Ok(self.tasks.clone())
+    }
+
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        self.tasks = tasks.to_vec();
+        Ok(())
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        Ok(self.notes.clone())
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        self.notes = notes.to_string();
+        Ok(())
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        Ok(self.settings.clone())
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        self.settings = Some(settings.clone());
+        Ok(())
+    }
+}
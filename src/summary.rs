@@ -0,0 +1,231 @@
+use crate::markdown;
+use crate::{Task, TaskState};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, TimeZone, Utc};
+
+/// The length of the period a summary covers.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Period {
+    #[default]
+    Day,
+    Week,
+}
+
+impl Period {
+    pub const ALL: [Period; 2] = [Period::Day, Period::Week];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Week => "week",
+        }
+    }
+}
+
+/// The first day of the period `back` periods before the one `today` is
+/// in. Weeks start on Monday.
+fn first_day(period: Period, back: u32, today: NaiveDate) -> NaiveDate {
+    match period {
+        Period::Day => today - Days::new(back.into()),
+        Period::Week => {
+            let monday = today - Days::new(today.weekday().num_days_from_monday().into());
+            monday - Days::new(u64::from(back) * 7)
+        }
+    }
+}
+
+fn start_of(day: NaiveDate) -> DateTime<Utc> {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map_or(midnight.and_utc(), |time| time.to_utc())
+}
+
+/// A title such as `Tuesday 2026-10-13` or `Week of 2026-10-12`.
+pub fn title(period: Period, back: u32, now: DateTime<Local>) -> String {
+    let first = first_day(period, back, now.date_naive());
+    match period {
+        Period::Day => first.format("%A %Y-%m-%d").to_string(),
+        Period::Week => format!("Week of {}", first.format("%Y-%m-%d")),
+    }
+}
+
+/// When a task was added. Its fields are all stamped when it is added and
+/// only move later, so the earliest stamp is the closest to that.
+fn added_at(task: &Task) -> DateTime<Utc> {
+    task.modified
+        .text
+        .min(task.modified.done)
+        .min(task.modified.state)
+}
+
+/// Whether `task` is a copy of a task done before it was added, as the
+/// reschedule buttons make. Tasks keep no record of how they were added,
+/// so a task added by hand with the same text as a done one counts too.
+fn is_rescheduled(task: &Task, tasks: &[Task]) -> bool {
+    tasks.iter().any(|other| {
+        other.task_id != task.task_id
+            && other.done
+            && other.task_text == task.task_text
+            && other.modified.done <= added_at(task)
+    })
+}
+
+/// A checklist section. Pending tasks are unchecked even if they were
+/// done after the period.
+fn section(report: &mut String, heading: &str, tasks: &[&Task], pending: bool) {
+    report.push_str(&format!("\n### {heading}\n\n"));
+    if tasks.is_empty() {
+        report.push_str("Nothing.\n");
+    }
+    for task in tasks {
+        let done = task.done && !pending;
+        let marker = match (done, task.state) {
+            (false, TaskState::Chosen) => " (active)",
+            (false, TaskState::Uncertain) => " (uncertain)",
+            _ => "",
+        };
+        let item = markdown::item(&Task {
+            done,
+            ..(*task).clone()
+        });
+        report.push_str(&format!("{item}{marker}\n"));
+    }
+}
+
+/// A Markdown summary of the tasks completed, added and rescheduled in the
+/// period `back` periods before the current one, and of those still
+/// pending at its end, all read from the tasks' timestamps.
+pub fn report(tasks: &[Task], period: Period, back: u32, now: DateTime<Local>) -> String {
+    let first = first_day(period, back, now.date_naive());
+    let start = start_of(first);
+    let end = match period {
+        Period::Day => start_of(first + Days::new(1)),
+        Period::Week => start_of(first + Days::new(7)),
+    };
+    let during = |time: DateTime<Utc>| start <= time && time < end;
+
+    let shown: Vec<&Task> = tasks.iter().rev().collect();
+    let completed: Vec<&Task> = shown
+        .iter()
+        .copied()
+        .filter(|task| task.done && during(task.modified.done))
+        .collect();
+    let (rescheduled, added): (Vec<&Task>, Vec<&Task>) = shown
+        .iter()
+        .copied()
+        .filter(|task| during(added_at(task)))
+        .partition(|task| is_rescheduled(task, tasks));
+    let pending: Vec<&Task> = shown
+        .iter()
+        .copied()
+        .filter(|task| added_at(task) < end && !(task.done && task.modified.done < end))
+        .collect();
+
+    let mut report = format!("## Summary: {}\n", title(period, back, now));
+    section(&mut report, "Completed", &completed, false);
+    section(&mut report, "Added", &added, false);
+    section(&mut report, "Rescheduled", &rescheduled, false);
+    section(&mut report, "Pending", &pending, true);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A time on a day of October 2026; the 12th is a Monday.
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .to_utc()
+    }
+
+    fn task(text: &str, added: DateTime<Utc>, done: Option<DateTime<Utc>>) -> Task {
+        let mut task = Task::new(text.to_string(), TaskState::Normal);
+        task.modified.text = added;
+        task.modified.state = added;
+        task.modified.done = done.unwrap_or(added);
+        task.done = done.is_some();
+        task
+    }
+
+    /// The items listed under `heading`.
+    fn items<'a>(report: &'a str, heading: &str) -> Vec<&'a str> {
+        let section = report
+            .split("\n### ")
+            .find(|section| section.starts_with(heading))
+            .unwrap_or_default();
+        section
+            .lines()
+            .filter_map(|line| line.strip_prefix("- ["))
+            .map(|item| &item[3..])
+            .collect()
+    }
+
+    fn tasks() -> Vec<Task> {
+        vec![
+            task("write report", at(9, 9, 0), Some(at(11, 23, 30))),
+            task("call bob", at(10, 9, 0), Some(at(13, 10, 0))),
+            task("pay rent", at(11, 9, 0), Some(at(12, 0, 30))),
+            task("water plants", at(12, 9, 0), None),
+            task("call bob", at(14, 10, 0), None),
+            task("buy bread", at(14, 11, 0), Some(at(14, 18, 0))),
+            task("plan trip", at(15, 9, 0), None),
+        ]
+    }
+
+    /// The report made at noon on Wednesday the 14th.
+    fn report_for(tasks: &[Task], period: Period, back: u32) -> String {
+        report(tasks, period, back, at(14, 12, 0).with_timezone(&Local))
+    }
+
+    #[test]
+    fn day_report_splits_tasks_at_midnight() {
+        let tasks = tasks();
+        let today = report_for(&tasks, Period::Day, 0);
+        assert!(today.starts_with("## Summary: Wednesday 2026-10-14\n"));
+        assert_eq!(items(&today, "Completed"), ["buy bread"]);
+        assert_eq!(items(&today, "Added"), ["buy bread"]);
+        assert_eq!(items(&today, "Rescheduled"), ["call bob"]);
+        assert_eq!(items(&today, "Pending"), ["call bob", "water plants"]);
+
+        let yesterday = report_for(&tasks, Period::Day, 1);
+        assert_eq!(items(&yesterday, "Completed"), ["call bob"]);
+        assert!(items(&yesterday, "Added").is_empty());
+        assert!(items(&yesterday, "Rescheduled").is_empty());
+        assert_eq!(items(&yesterday, "Pending"), ["water plants"]);
+    }
+
+    #[test]
+    fn week_report_starts_on_monday() {
+        let tasks = tasks();
+        let week = report_for(&tasks, Period::Week, 0);
+        assert!(week.starts_with("## Summary: Week of 2026-10-12\n"));
+        assert_eq!(
+            items(&week, "Completed"),
+            ["buy bread", "pay rent", "call bob"]
+        );
+        assert_eq!(
+            items(&week, "Added"),
+            ["plan trip", "buy bread", "water plants"]
+        );
+        assert_eq!(items(&week, "Rescheduled"), ["call bob"]);
+        assert_eq!(
+            items(&week, "Pending"),
+            ["plan trip", "call bob", "water plants"]
+        );
+
+        let last_week = report_for(&tasks, Period::Week, 1);
+        assert!(last_week.starts_with("## Summary: Week of 2026-10-05\n"));
+        assert_eq!(items(&last_week, "Completed"), ["write report"]);
+        assert_eq!(
+            items(&last_week, "Added"),
+            ["pay rent", "call bob", "write report"]
+        );
+        assert!(items(&last_week, "Rescheduled").is_empty());
+        // done half an hour after the week ended
+        assert_eq!(items(&last_week, "Pending"), ["pay rent", "call bob"]);
+    }
+}
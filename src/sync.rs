@@ -0,0 +1,150 @@
+use crate::{Task, TaskState};
+use std::path::{Path, PathBuf};
+
+pub const MERGE_LOG_FILENAME: &str = "sync-merge.log";
+
+/// Conflicting copies left next to the databases by a folder sync tool.
+#[derive(Default)]
+pub struct ConflictCopies {
+    pub tasks: Vec<(PathBuf, Vec<Task>)>,
+    pub notes: Vec<(PathBuf, String)>,
+}
+
+impl ConflictCopies {
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty() && self.notes.is_empty()
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.tasks
+            .iter()
+            .map(|(path, _)| path.clone())
+            .chain(self.notes.iter().map(|(path, _)| path.clone()))
+            .collect()
+    }
+}
+
+/// Matches copies such as `database.sync-conflict-20240101-120000-ABCDEFG.json`
+/// (Syncthing) or `database (conflicted copy 2024-01-01).json` (Nextcloud,
+/// Dropbox) for the database file `filename`.
+pub fn is_conflict_copy(name: &str, filename: &str) -> bool {
+    let Some(stem) = filename.strip_suffix(".json") else {
+        return false;
+    };
+    let rest = match name.strip_prefix(stem) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    name != filename
+        && rest.ends_with(".json")
+        && (rest.starts_with('.') || rest.starts_with(' '))
+        && rest.to_lowercase().contains("conflict")
+}
+
+pub fn find_conflict_copies(dir: &Path, filename: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| is_conflict_copy(name, filename))
+        })
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn label(task: &Task) -> String {
+    let marker = match task.state {
+        TaskState::Normal => "",
+        TaskState::Chosen => "!",
+        TaskState::Uncertain => "?",
+    };
+    format!("'{}{marker}'", task.task_text)
+}
+
+/// Merges each conflicting copy into `tasks` and `notes` and returns a
+/// report of what changed.
+///
+/// Tasks are matched by `task_id` and every field takes whichever side
+/// modified it last. Tasks that exist in only one copy are kept, since a
+/// copy cannot tell a deletion apart from a task it never saw. Note lines
+/// missing from `notes` are appended below a separator.
+pub fn merge_copies(tasks: &mut Vec<Task>, notes: &mut String, copies: &ConflictCopies) -> String {
+    let mut report = vec![];
+
+    for (path, copy) in &copies.tasks {
+        report.push(format!("{}:", path.display()));
+        let before = report.len();
+
+        for theirs in copy {
+            match tasks.iter_mut().find(|t| t.task_id == theirs.task_id) {
+                Some(task) => {
+                    let mut fields = vec![];
+                    if theirs.modified.text > task.modified.text {
+                        task.task_text = theirs.task_text.clone();
+                        task.modified.text = theirs.modified.text;
+                        fields.push("text");
+                    }
+                    if theirs.modified.done > task.modified.done {
+                        task.done = theirs.done;
+                        task.modified.done = theirs.modified.done;
+                        fields.push("done");
+                    }
+                    if theirs.modified.state > task.modified.state {
+                        task.state = theirs.state;
+                        task.modified.state = theirs.modified.state;
+                        fields.push("state");
+                    }
+                    if !fields.is_empty() {
+                        report.push(format!(
+                            "  updated {} of {}",
+                            fields.join(", "),
+                            label(task)
+                        ));
+                    }
+                }
+                None => {
+                    report.push(format!("  added {}", label(theirs)));
+                    tasks.push(theirs.clone());
+                }
+            }
+        }
+
+        let missing: Vec<String> = tasks
+            .iter()
+            .filter(|t| !copy.iter().any(|c| c.task_id == t.task_id))
+            .map(label)
+            .collect();
+        if !missing.is_empty() {
+            report.push(format!("  kept, not in this copy: {}", missing.join(", ")));
+        }
+        if report.len() == before {
+            report.push("  no changes".to_string());
+        }
+    }
+
+    for (path, copy) in &copies.notes {
+        let new_lines: Vec<&str> = copy
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !notes.lines().any(|l| l == *line))
+            .collect();
+        if new_lines.is_empty() {
+            report.push(format!("{}: no changes", path.display()));
+        } else {
+            report.push(format!(
+                "{}: appended {} note line(s)",
+                path.display(),
+                new_lines.len()
+            ));
+            notes.push_str("\n\n---\n\n");
+            notes.push_str(&new_lines.join("\n"));
+        }
+    }
+
+    report.join("\n")
+}
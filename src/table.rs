@@ -0,0 +1,368 @@
+use crate::{Task, TaskState, ics, org, taskwarrior, todotxt};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// File formats the import/export dialog reads and writes. Formats other
+/// than CSV and JSON are read into a table with the exported columns,
+/// except those that `keep_ids`. HTML pages are only written.
+#[derive(Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Csv,
+    Json,
+    /// iCalendar VTODOs
+    Ics,
+    /// `task export` output
+    Taskwarrior,
+    /// Org-mode headlines, with the notes when exported
+    Org,
+    /// One task per line, with its `id:`
+    TodoTxt,
+    /// A page of the shown tasks and the rendered notes
+    Html,
+}
+
+impl Format {
+    pub const ALL: [Format; 7] = [
+        Format::Csv,
+        Format::Json,
+        Format::Ics,
+        Format::Taskwarrior,
+        Format::Org,
+        Format::TodoTxt,
+        Format::Html,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Ics => "ics",
+            Format::Taskwarrior => "taskwarrior",
+            Format::Org => "org",
+            Format::TodoTxt => "todo.txt",
+            Format::Html => "html",
+        }
+    }
+
+    pub fn default_filename(self) -> &'static str {
+        match self {
+            Format::Csv => "tasks.csv",
+            Format::Json => "tasks.json",
+            Format::Ics => "tasks.ics",
+            Format::Taskwarrior => "taskwarrior.json",
+            Format::Org => "tasks.org",
+            Format::TodoTxt => todotxt::TODO_TXT_FILENAME,
+            Format::Html => "tasks.html",
+        }
+    }
+
+    pub fn importable(self) -> bool {
+        self != Format::Html
+    }
+
+    /// Whether files of this format hold whole tasks with their ids. Those
+    /// are imported as they are, replacing tasks with the same id, instead
+    /// of through a table.
+    pub fn keeps_ids(self) -> bool {
+        matches!(self, Format::Taskwarrior | Format::TodoTxt)
+    }
+}
+
+/// The `Task` field a column is read into.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Field {
+    Ignore,
+    Text,
+    Done,
+    State,
+    TaskId,
+}
+
+impl Field {
+    pub const ALL: [Field; 5] = [
+        Field::Ignore,
+        Field::Text,
+        Field::Done,
+        Field::State,
+        Field::TaskId,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Ignore => "ignore",
+            Field::Text => "task_text",
+            Field::Done => "done",
+            Field::State => "state",
+            Field::TaskId => "task_id",
+        }
+    }
+
+    /// A guess from a column header such as `Title` or `completed`.
+    fn guess(header: &str) -> Field {
+        match header.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+            "task_text" | "text" | "task" | "title" | "name" | "description" | "summary" => {
+                Field::Text
+            }
+            "done" | "completed" | "complete" | "finished" | "status" => Field::Done,
+            "state" | "priority" => Field::State,
+            "task_id" | "id" | "uuid" => Field::TaskId,
+            _ => Field::Ignore,
+        }
+    }
+}
+
+/// Rows read from a CSV or JSON file, all values as text.
+#[derive(Default, Clone)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// The columns CSV export writes.
+    fn from_tasks(tasks: &[Task]) -> Table {
+        Table {
+            headers: ["task_id", "task_text", "done", "state"].map(String::from).to_vec(),
+            rows: tasks
+                .iter()
+                .map(|task| {
+                    vec![
+                        task.task_id.to_string(),
+                        task.task_text.clone(),
+                        task.done.to_string(),
+                        state_name(task.state).to_string(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// A guessed field for every column. Only the first column guessed as
+    /// each field is used.
+    pub fn guess_mapping(&self) -> Vec<Field> {
+        let mut seen = vec![];
+        self.headers
+            .iter()
+            .map(|header| match Field::guess(header) {
+                field if seen.contains(&field) => Field::Ignore,
+                field => {
+                    seen.push(field);
+                    field
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn parse(format: Format, data: &str) -> Result<Table, String> {
+    match format {
+        Format::Csv => parse_csv(data),
+        Format::Json => parse_json(data),
+        Format::Ics => ics::import(data).map(|tasks| Table::from_tasks(&tasks)),
+        Format::Taskwarrior => parse_tasks(format, data).map(|tasks| Table::from_tasks(&tasks)),
+        Format::Org => Ok(Table::from_tasks(&org::import(data))),
+        Format::TodoTxt => parse_tasks(format, data).map(|tasks| Table::from_tasks(&tasks)),
+        Format::Html => Err("html pages can only be exported".to_string()),
+    }
+}
+
+/// The tasks in a file of a format that `keeps_ids`.
+pub fn parse_tasks(format: Format, data: &str) -> Result<Vec<Task>, String> {
+    match format {
+        Format::Taskwarrior => taskwarrior::import(data),
+        Format::TodoTxt => Ok(todotxt::import(data)),
+        _ => Err(format!("{} files are read into a table", format.name())),
+    }
+}
+
+fn parse_csv(data: &str) -> Result<Table, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let rows = reader
+        .records()
+        .map(|record| {
+            record
+                .map(|record| record.iter().map(str::to_string).collect())
+                .map_err(|e| e.to_string())
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Table { headers, rows })
+}
+
+/// An array of objects; the columns are every key any object has.
+fn parse_json(data: &str) -> Result<Table, String> {
+    let objects: Vec<Map<String, Value>> =
+        serde_json::from_str(data).map_err(|e| format!("expected an array of objects: {e}"))?;
+
+    let mut headers: Vec<String> = vec![];
+    for key in objects.iter().flat_map(|object| object.keys()) {
+        if !headers.contains(key) {
+            headers.push(key.clone());
+        }
+    }
+    let rows = objects
+        .iter()
+        .map(|object| {
+            headers
+                .iter()
+                .map(|key| match object.get(key) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(text)) => text.clone(),
+                    Some(value) => value.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    Ok(Table { headers, rows })
+}
+
+fn parse_done(value: &str) -> bool {
+    matches!(
+        value.trim().to_lowercase().as_str(),
+        "true" | "1" | "yes" | "y" | "x" | "done" | "completed" | "complete"
+    )
+}
+
+fn parse_state(value: &str) -> TaskState {
+    match value.trim().to_lowercase().as_str() {
+        "chosen" | "active" | "!" | "a" | "high" => TaskState::Chosen,
+        "uncertain" | "?" => TaskState::Uncertain,
+        _ => TaskState::Normal,
+    }
+}
+
+fn state_name(state: TaskState) -> &'static str {
+    match state {
+        TaskState::Normal => "normal",
+        TaskState::Chosen => "chosen",
+        TaskState::Uncertain => "uncertain",
+    }
+}
+
+/// Which rows count as already being in the list.
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Dedupe {
+    #[default]
+    Text,
+    TaskId,
+    None,
+}
+
+impl Dedupe {
+    pub const ALL: [Dedupe; 3] = [Dedupe::Text, Dedupe::TaskId, Dedupe::None];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dedupe::Text => "same text",
+            Dedupe::TaskId => "same task_id",
+            Dedupe::None => "keep all",
+        }
+    }
+}
+
+/// What importing a table would do, shown before anything is changed.
+#[derive(Default)]
+pub struct Plan {
+    pub tasks: Vec<Task>,
+    pub duplicates: usize,
+    /// Rows without any text.
+    pub empty: usize,
+}
+
+/// The tasks `table` adds to `existing` with `mapping`, skipping rows that
+/// are duplicates of existing tasks or of earlier rows. Rows without a
+/// valid `task_id`, or with one already taken, get a new one.
+pub fn plan(table: &Table, mapping: &[Field], dedupe: Dedupe, existing: &[Task]) -> Plan {
+    let column = |field| mapping.iter().position(|f| *f == field);
+    let (text, done, state, task_id) = (
+        column(Field::Text),
+        column(Field::Done),
+        column(Field::State),
+        column(Field::TaskId),
+    );
+
+    let mut texts: HashSet<String> = existing.iter().map(|t| t.task_text.clone()).collect();
+    let mut ids: HashSet<Uuid> = existing.iter().map(|t| t.task_id).collect();
+    let mut plan = Plan::default();
+    for row in &table.rows {
+        let value = |column: Option<usize>| {
+            column
+                .and_then(|i| row.get(i))
+                .map(|value| value.trim())
+                .unwrap_or("")
+        };
+        if value(text).is_empty() {
+            plan.empty += 1;
+            continue;
+        }
+
+        let mut task = Task::new(value(text).to_string(), parse_state(value(state)));
+        task.done = parse_done(value(done));
+        if let Ok(id) = Uuid::parse_str(value(task_id)) {
+            task.task_id = id;
+        }
+
+        let duplicate = match dedupe {
+            Dedupe::Text => texts.contains(&task.task_text),
+            Dedupe::TaskId => ids.contains(&task.task_id),
+            Dedupe::None => false,
+        };
+        if duplicate {
+            plan.duplicates += 1;
+            continue;
+        }
+        // imported tasks never replace existing ones
+        if ids.contains(&task.task_id) {
+            task.task_id = Uuid::new_v4();
+        }
+        ids.insert(task.task_id);
+        texts.insert(task.task_text.clone());
+        plan.tasks.push(task);
+    }
+    plan
+}
+
+/// The tasks with one row per task, under headers the importer maps back
+/// to the same fields. Only org files include `notes`. HTML pages need the
+/// theme and are made by `html::export` instead.
+pub fn export(format: Format, tasks: &[Task], notes: &str) -> Result<String, String> {
+    match format {
+        Format::Csv => {
+            let table = Table::from_tasks(tasks);
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for record in std::iter::once(&table.headers).chain(&table.rows) {
+                writer.write_record(record).map_err(|e| e.to_string())?;
+            }
+            let data = writer.into_inner().map_err(|e| e.to_string())?;
+            String::from_utf8(data).map_err(|e| e.to_string())
+        }
+        Format::Json => {
+            let objects: Vec<Map<String, Value>> = tasks
+                .iter()
+                .map(|task| {
+                    let mut object = Map::new();
+                    object.insert("task_id".into(), task.task_id.to_string().into());
+                    object.insert("task_text".into(), task.task_text.clone().into());
+                    object.insert("done".into(), task.done.into());
+                    object.insert("state".into(), state_name(task.state).into());
+                    object
+                })
+                .collect();
+            Ok(serde_json::to_string_pretty(&objects).expect("failed to serialize"))
+        }
+        Format::Ics => Ok(ics::export(tasks)),
+        Format::Taskwarrior => Ok(taskwarrior::export(tasks)),
+        Format::Org => Ok(org::export(tasks, notes)),
+        Format::TodoTxt => Ok(todotxt::export(tasks)),
+        Format::Html => Err("html pages are exported with the theme".to_string()),
+    }
+}
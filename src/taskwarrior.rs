@@ -0,0 +1,172 @@
+use crate::{Modified, Task, TaskState};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Urgency from which an imported task counts as chosen. Taskwarrior's
+/// defaults give about 6 for a high priority and up to 12 for being due.
+const CHOSEN_URGENCY: f64 = 8.0;
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// A task as `task export` writes it and `task import` reads it, limited
+/// to the attributes cardamom-chai maps.
+#[derive(Serialize, Deserialize)]
+struct Exported {
+    uuid: Uuid,
+    description: String,
+    status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing)]
+    urgency: Option<f64>,
+}
+
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(date, DATE_FORMAT)
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+/// The tasks in `task export` output. Tags become `+tag` words, the
+/// project a `project:` word and the due date a `due:YYYY-MM-DD` word of
+/// the task text. Deleted tasks are skipped.
+pub fn import(json: &str) -> Result<Vec<Task>, String> {
+    let exported: Vec<Exported> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let tasks = exported
+        .into_iter()
+        .filter(|task| task.status != "deleted")
+        .map(|task| {
+            let mut words = vec![task.description.trim().to_string()];
+            words.extend(task.tags.iter().map(|tag| format!("+{tag}")));
+            if let Some(project) = &task.project {
+                words.push(format!("project:{project}"));
+            }
+            if let Some(due) = task.due.as_deref().and_then(parse_date) {
+                words.push(format!("due:{}", due.format("%Y-%m-%d")));
+            }
+
+            let state = match (task.priority.as_deref(), task.urgency) {
+                (Some("H"), _) => TaskState::Chosen,
+                (Some("L"), _) => TaskState::Uncertain,
+                (_, Some(urgency)) if urgency >= CHOSEN_URGENCY => TaskState::Chosen,
+                (_, Some(urgency)) if urgency < 0.0 => TaskState::Uncertain,
+                _ => TaskState::Normal,
+            };
+            let now = Utc::now();
+            let modified = task.modified.as_deref().and_then(parse_date).unwrap_or(now);
+            Task {
+                task_id: task.uuid,
+                task_text: words.join(" "),
+                done: task.status == "completed",
+                state,
+                modified: Modified {
+                    text: modified,
+                    done: task.end.as_deref().and_then(parse_date).unwrap_or(modified),
+                    state: modified,
+                },
+            }
+        })
+        .collect();
+    Ok(tasks)
+}
+
+/// A JSON array `task import` reads, with the words `import` reads out of
+/// the text turned back into attributes. Chosen tasks get a high priority
+/// and uncertain ones a low one.
+pub fn export(tasks: &[Task]) -> String {
+    let exported: Vec<Exported> = tasks
+        .iter()
+        .map(|task| {
+            let mut description = vec![];
+            let mut tags = vec![];
+            let mut project = None;
+            let mut due = None;
+            for word in task.task_text.split_whitespace() {
+                if let Some(tag) = word.strip_prefix('+').filter(|tag| !tag.is_empty()) {
+                    tags.push(tag.to_string());
+                } else if let Some(name) = word.strip_prefix("project:") {
+                    project = Some(name.to_string());
+                } else if let Some(date) = word
+                    .strip_prefix("due:")
+                    .and_then(|date| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                {
+                    due = date.and_hms_opt(0, 0, 0).map(|date| format_date(date.and_utc()));
+                } else {
+                    description.push(word);
+                }
+            }
+
+            let last_modified = task
+                .modified
+                .text
+                .max(task.modified.done)
+                .max(task.modified.state);
+            Exported {
+                uuid: task.task_id,
+                description: description.join(" "),
+                status: if task.done { "completed" } else { "pending" }.to_string(),
+                entry: Some(format_date(task.modified.text)),
+                modified: Some(format_date(last_modified)),
+                end: task.done.then(|| format_date(task.modified.done)),
+                due,
+                project,
+                tags,
+                priority: match task.state {
+                    TaskState::Chosen => Some("H".to_string()),
+                    TaskState::Uncertain => Some("L".to_string()),
+                    TaskState::Normal => None,
+                },
+                urgency: None,
+            }
+        })
+        .collect();
+    serde_json::to_string_pretty(&exported).expect("failed to serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change_times_survive_export_and_import() {
+        let date = |date: &str| parse_date(date).unwrap();
+        let task = Task {
+            task_id: Uuid::new_v4(),
+            task_text: "file taxes +home project:money due:2026-04-15".to_string(),
+            done: true,
+            state: TaskState::Chosen,
+            modified: Modified {
+                text: date("20260101T090000Z"),
+                done: date("20260301T120000Z"),
+                state: date("20260301T120000Z"),
+            },
+        };
+
+        let imported = import(&export(std::slice::from_ref(&task))).unwrap();
+        let [imported] = &imported[..] else {
+            panic!("expected one task");
+        };
+        assert_eq!(imported.task_id, task.task_id);
+        assert_eq!(imported.task_text, task.task_text);
+        assert!(imported.done && imported.state == TaskState::Chosen);
+        assert_eq!(imported.modified.done, task.modified.done);
+        assert_eq!(imported.modified.text, date("20260301T120000Z"));
+    }
+}
@@ -0,0 +1,251 @@
+use eframe::egui::{self, Color32};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+
+pub const EXPORTED_THEME: &str = "current-theme";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub dark: bool,
+    pub visuals: VisualColors,
+    pub tasks: TaskColors,
+    pub text: TextSizes,
+}
+
+/// Overrides applied on top of egui's light or dark `Visuals`.
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisualColors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panel_fill: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_fill: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extreme_bg: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faint_bg: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hyperlink: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Color>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaskColors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normal: Option<Color>,
+    pub chosen: Color,
+    pub uncertain: Color,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextSizes {
+    pub heading: f32,
+    pub body: f32,
+    pub monospace: f32,
+    pub button: f32,
+    pub small: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::light()
+    }
+}
+
+impl Default for TaskColors {
+    fn default() -> Self {
+        TaskColors {
+            normal: None,
+            chosen: Color(Color32::from_rgb(32, 159, 181)),
+            uncertain: Color(Color32::from_rgb(234, 118, 203)),
+        }
+    }
+}
+
+impl Default for TextSizes {
+    fn default() -> Self {
+        TextSizes {
+            heading: 24.0,
+            body: 15.0,
+            monospace: 14.0,
+            button: 15.0,
+            small: 10.0,
+        }
+    }
+}
+
+impl Theme {
+    pub fn light() -> Self {
+        Theme {
+            dark: false,
+            visuals: VisualColors::default(),
+            tasks: TaskColors::default(),
+            text: TextSizes::default(),
+        }
+    }
+
+    pub fn dark() -> Self {
+        Theme {
+            dark: true,
+            visuals: VisualColors::default(),
+            tasks: TaskColors {
+                normal: None,
+                chosen: Color(Color32::from_rgb(116, 199, 236)),
+                uncertain: Color(Color32::from_rgb(245, 194, 231)),
+            },
+            text: TextSizes::default(),
+        }
+    }
+
+    pub fn chai() -> Self {
+        Theme {
+            dark: false,
+            visuals: VisualColors {
+                text: Some(Color(Color32::from_rgb(76, 52, 38))),
+                panel_fill: Some(Color(Color32::from_rgb(245, 236, 222))),
+                window_fill: Some(Color(Color32::from_rgb(245, 236, 222))),
+                extreme_bg: Some(Color(Color32::from_rgb(253, 248, 240))),
+                faint_bg: Some(Color(Color32::from_rgb(238, 226, 208))),
+                hyperlink: Some(Color(Color32::from_rgb(166, 90, 44))),
+                selection: Some(Color(Color32::from_rgb(214, 176, 132))),
+            },
+            tasks: TaskColors {
+                normal: None,
+                chosen: Color(Color32::from_rgb(178, 85, 34)),
+                uncertain: Color(Color32::from_rgb(122, 110, 160)),
+            },
+            text: TextSizes::default(),
+        }
+    }
+
+    pub fn bundled() -> Vec<(&'static str, Theme)> {
+        vec![
+            ("light", Theme::light()),
+            ("dark", Theme::dark()),
+            ("chai", Theme::chai()),
+        ]
+    }
+
+    pub fn visuals(&self) -> egui::Visuals {
+        let mut visuals = if self.dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        let v = &self.visuals;
+
+        visuals.override_text_color = v.text.map(|c| c.0);
+        if let Some(c) = v.panel_fill {
+            visuals.panel_fill = c.0;
+        }
+        if let Some(c) = v.window_fill {
+            visuals.window_fill = c.0;
+        }
+        if let Some(c) = v.extreme_bg {
+            visuals.extreme_bg_color = c.0;
+        }
+        if let Some(c) = v.faint_bg {
+            visuals.faint_bg_color = c.0;
+        }
+        if let Some(c) = v.hyperlink {
+            visuals.hyperlink_color = c.0;
+        }
+        if let Some(c) = v.selection {
+            visuals.selection.bg_fill = c.0;
+        }
+        visuals
+    }
+
+    pub fn text_styles(&self) -> std::collections::BTreeMap<egui::TextStyle, egui::FontId> {
+        let t = &self.text;
+        [
+            (
+                egui::TextStyle::Heading,
+                egui::FontId::new(t.heading, egui::FontFamily::Proportional),
+            ),
+            (
+                egui::TextStyle::Body,
+                egui::FontId::new(t.body, egui::FontFamily::Proportional),
+            ),
+            (
+                egui::TextStyle::Monospace,
+                egui::FontId::new(t.monospace, egui::FontFamily::Monospace),
+            ),
+            (
+                egui::TextStyle::Button,
+                egui::FontId::new(t.button, egui::FontFamily::Proportional),
+            ),
+            (
+                egui::TextStyle::Small,
+                egui::FontId::new(t.small, egui::FontFamily::Proportional),
+            ),
+        ]
+        .into()
+    }
+}
+
+/// An sRGB color stored as `#rrggbb` or `#rrggbbaa` in theme files.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Color(pub Color32);
+
+impl Color {
+    /// `#rrggbb`, or `#rrggbbaa` when not opaque, as in CSS.
+    pub fn hex(self) -> String {
+        let [r, g, b, a] = self.0.to_srgba_unmultiplied();
+        if a == 255 {
+            format!("#{r:02x}{g:02x}{b:02x}")
+        } else {
+            format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+        }
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Color32::from_hex(s.trim())
+            .map(Color)
+            .map_err(|_| serde::de::Error::custom(format!("invalid color {s:?}")))
+    }
+}
+
+pub fn theme_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.json"))
+}
+
+/// Writes the bundled themes into `dir` unless a file of the same name exists.
+pub fn install_bundled(dir: &Path) {
+    std::fs::create_dir_all(dir).ok();
+    for (name, theme) in Theme::bundled() {
+        let path = theme_path(dir, name);
+        if !path.exists() {
+            let json = serde_json::to_string_pretty(&theme).expect("failed to serialize");
+            std::fs::write(path, json).ok();
+        }
+    }
+}
+
+pub fn list(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
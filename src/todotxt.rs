@@ -0,0 +1,401 @@
+use crate::settings::Settings;
+use crate::storage::{self, Storage};
+use crate::{Modified, Task, TaskState};
+use chrono::{NaiveDate, Utc};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const TODO_TXT_FILENAME: &str = "todo.txt";
+pub const DONE_TXT_FILENAME: &str = "done.txt";
+
+/// Task fields carried in `key:value` tokens, which other todo.txt tools
+/// leave alone.
+const ID_KEY: &str = "id:";
+const STATE_KEY: &str = "state:";
+/// Priority of a completed task, which loses its `(A)` when marked done.
+const PRIORITY_KEY: &str = "pri:";
+
+fn parse_date(token: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(token, "%Y-%m-%d").ok()
+}
+
+/// `(A)` through `(Z)`.
+fn is_priority(token: &str) -> bool {
+    matches!(token.as_bytes(), [b'(', letter, b')'] if letter.is_ascii_uppercase())
+}
+
+/// One todo.txt line for `task`. `+project` and `@context` tags are part
+/// of the task text and are written as they are.
+pub fn to_line(task: &Task) -> String {
+    format_line(task, 'A', None)
+}
+
+/// `to_line`, keeping the priority letter and creation date of the line
+/// `task` was read from.
+fn rewrite(task: &Task, original: &str) -> String {
+    let mut tokens = original.split_whitespace().peekable();
+    let mut priority = None;
+    if tokens.next_if_eq(&"x").is_some() {
+        // completion date
+        tokens.next_if(|token| parse_date(token).is_some());
+    } else if let Some(token) = tokens.next_if(|token| is_priority(token)) {
+        priority = token.chars().nth(1);
+    }
+    let created = tokens.peek().and_then(|token| parse_date(token));
+    let priority = priority.or_else(|| {
+        original
+            .split_whitespace()
+            .find_map(|token| token.strip_prefix(PRIORITY_KEY)?.chars().next())
+            .filter(char::is_ascii_uppercase)
+    });
+    format_line(task, priority.unwrap_or('A'), created)
+}
+
+fn format_line(task: &Task, priority: char, created: Option<NaiveDate>) -> String {
+    // text starting with an `x`, a priority or a date would be read back as
+    // the line's own, unless a creation date comes first
+    let first = task.task_text.split_whitespace().next().unwrap_or_default();
+    let ambiguous = first == "x" || is_priority(first) || parse_date(first).is_some();
+    let created = created.or_else(|| ambiguous.then(|| task.modified.text.date_naive()));
+
+    let mut line = String::new();
+    if task.done {
+        line.push_str(&format!("x {} ", task.modified.done.format("%Y-%m-%d")));
+    } else if task.state == TaskState::Chosen {
+        line.push_str(&format!("({priority}) "));
+    }
+    if let Some(created) = created {
+        line.push_str(&format!("{} ", created.format("%Y-%m-%d")));
+    }
+    line.push_str(task.task_text.trim());
+    if task.done && task.state == TaskState::Chosen {
+        line.push_str(&format!(" {PRIORITY_KEY}{priority}"));
+    }
+    if task.state == TaskState::Uncertain {
+        line.push_str(&format!(" {STATE_KEY}uncertain"));
+    }
+    line.push_str(&format!(" {ID_KEY}{}", task.task_id));
+    line
+}
+
+/// The task on a todo.txt line, or `None` for blank lines. Any priority
+/// marks the task chosen; lines without an `id:` get the nil id.
+pub fn from_line(line: &str) -> Option<Task> {
+    let mut tokens = line.split_whitespace().peekable();
+    tokens.peek()?;
+
+    let now = Utc::now();
+    let mut task = Task {
+        modified: Modified {
+            text: now,
+            done: now,
+            state: now,
+        },
+        ..Task::default()
+    };
+
+    if tokens.next_if_eq(&"x").is_some() {
+        task.done = true;
+        if let Some(date) = tokens.peek().and_then(|token| parse_date(token)) {
+            tokens.next();
+            task.modified.done = date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        }
+    } else if tokens.next_if(|token| is_priority(token)).is_some() {
+        task.state = TaskState::Chosen;
+    }
+    // creation date
+    tokens.next_if(|token| parse_date(token).is_some());
+
+    let mut words = vec![];
+    let mut task_id = None;
+    for token in tokens {
+        if let Some(id) = token.strip_prefix(ID_KEY).and_then(|id| Uuid::parse_str(id).ok()) {
+            task_id = Some(id);
+        } else if token.strip_prefix(STATE_KEY) == Some("uncertain") {
+            task.state = TaskState::Uncertain;
+        } else if token.starts_with(PRIORITY_KEY) && task.done {
+            task.state = TaskState::Chosen;
+        } else {
+            words.push(token);
+        }
+    }
+    task.task_id = task_id.unwrap_or_default();
+    task.task_text = words.join(" ");
+    Some(task)
+}
+
+pub fn export(tasks: &[Task]) -> String {
+    tasks.iter().map(|task| to_line(task) + "\n").collect()
+}
+
+/// Imported tasks without an `id:` are new ones.
+pub fn import(text: &str) -> Vec<Task> {
+    text.lines()
+        .filter_map(from_line)
+        .map(|mut task| {
+            if task.task_id.is_nil() {
+                task.task_id = Uuid::new_v4();
+            }
+            task
+        })
+        .collect()
+}
+
+/// The tasks in a todo.txt file with the lines they were read from.
+fn parse_file(text: &str) -> Vec<(Task, &str)> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    text.lines()
+        .filter_map(|line| {
+            let mut task = from_line(line)?;
+            if task.task_id.is_nil() {
+                let occurrence = occurrences.entry(line.trim()).or_default();
+                task.task_id = storage::line_id(line, *occurrence);
+                *occurrence += 1;
+            }
+            Some((task, line))
+        })
+        .collect()
+}
+
+fn has_id(line: &str, task: &Task) -> bool {
+    line.split_whitespace()
+        .any(|token| token == format!("{ID_KEY}{}", task.task_id))
+}
+
+/// The tasks in `todo` followed by those in `done`, the contents of
+/// `todo.txt` and `done.txt`.
+pub fn parse(todo: &str, done: &str, known: &[Task]) -> Vec<Task> {
+    parse_file(todo)
+        .into_iter()
+        .chain(parse_file(done))
+        .map(|(mut task, _)| {
+            storage::carry_over(&mut task, known);
+            task
+        })
+        .collect()
+}
+
+/// Tasks in a `todo.txt` and the `done.txt` next to it, which stay
+/// editable by hand and by other todo.txt tools. Lines keep their unknown
+/// tokens, and are written back unchanged unless their task changed.
+/// Saving gives every line an `id:`, so tasks keep their identity when
+/// the file is edited elsewhere.
+/// Notes and settings are kept as JSON in `dir`.
+pub struct TodoTxt {
+    todo_path: PathBuf,
+    done_path: PathBuf,
+    json: storage::Json,
+    /// The tasks as last loaded or saved.
+    known: Vec<Task>,
+}
+
+impl TodoTxt {
+    pub fn new(todo_path: &Path, dir: &Path) -> TodoTxt {
+        TodoTxt {
+            todo_path: todo_path.to_path_buf(),
+            done_path: todo_path.with_file_name(DONE_TXT_FILENAME),
+            json: storage::Json::new(dir),
+            known: vec![],
+        }
+    }
+}
+
+impl Storage for TodoTxt {
+    fn load_tasks(&mut self) -> Result<Vec<Task>, String> {
+        let todo = storage::read_or_empty(&self.todo_path)?;
+        let done = storage::read_or_empty(&self.done_path)?;
+        self.known = parse(&todo, &done, &self.known);
+        Ok(self.known.clone())
+    }
+
+    /// Tasks stay in the file they were read from, except that reopened
+    /// tasks move from `done.txt` back to `todo.txt`.
+    fn save_tasks(&mut self, tasks: &[Task]) -> Result<(), String> {
+        let old_todo = storage::read_or_empty(&self.todo_path)?;
+        let old_done = storage::read_or_empty(&self.done_path)?;
+        let originals: HashMap<Uuid, (Task, &str, bool)> = parse_file(&old_todo)
+            .into_iter()
+            .map(|(task, line)| (task.task_id, (task, line, false)))
+            .chain(
+                parse_file(&old_done)
+                    .into_iter()
+                    .map(|(task, line)| (task.task_id, (task, line, true))),
+            )
+            .collect();
+
+        let mut todo = String::new();
+        let mut done = String::new();
+        for task in tasks {
+            let original = originals.get(&task.task_id);
+            let line = match original {
+                Some((parsed, line, _)) if storage::same_fields(parsed, task) && has_id(line, task) => {
+                    line.to_string()
+                }
+                Some((_, line, _)) => rewrite(task, line),
+                None => to_line(task),
+            };
+            let file = match original {
+                Some((_, _, true)) if task.done => &mut done,
+                _ => &mut todo,
+            };
+            file.push_str(&line);
+            file.push('\n');
+        }
+
+        for (path, old, new) in [
+            (&self.todo_path, &old_todo, &todo),
+            (&self.done_path, &old_done, &done),
+        ] {
+            if old != new {
+                std::fs::write(path, new).map_err(|e| format!("{}: {e}", path.display()))?;
+            }
+        }
+        self.known = tasks.to_vec();
+        Ok(())
+    }
+
+    fn load_notes(&mut self) -> Result<String, String> {
+        self.json.load_notes()
+    }
+
+    fn save_notes(&mut self, notes: &str) -> Result<(), String> {
+        self.json.save_notes(notes)
+    }
+
+    fn load_settings(&mut self) -> Result<Option<Settings>, String> {
+        self.json.load_settings()
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        self.json.save_settings(settings)
+    }
+
+    /// The file is shared with other tools, so its tasks win.
+    fn keeps_existing(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALL: &str = "00000000-0000-0000-0000-000000000001";
+    const WATER: &str = "00000000-0000-0000-0000-000000000002";
+    const RENT: &str = "00000000-0000-0000-0000-000000000003";
+
+    fn todo_txt(todo: &str, done: &str) -> (TodoTxt, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("todotxt-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(TODO_TXT_FILENAME), todo).unwrap();
+        std::fs::write(dir.join(DONE_TXT_FILENAME), done).unwrap();
+        (TodoTxt::new(&dir.join(TODO_TXT_FILENAME), &dir), dir)
+    }
+
+    fn read(dir: &Path, filename: &str) -> String {
+        std::fs::read_to_string(dir.join(filename)).unwrap()
+    }
+
+    #[test]
+    fn unchanged_lines_are_written_back_as_they_were() {
+        let todo = format!(
+            "(B) 2026-01-02 call mom +family @phone due:2026-02-01 foo:bar id:{CALL}\n\
+             water plants state:uncertain rec:1w id:{WATER}\n"
+        );
+        let done = format!("x 2026-03-04 2026-01-01 pay rent +home pri:C t:2026-03-01 id:{RENT}\n");
+        let (mut store, dir) = todo_txt(&todo, &done);
+
+        let tasks = store.load_tasks().unwrap();
+        store.save_tasks(&tasks).unwrap();
+        assert_eq!(read(&dir, TODO_TXT_FILENAME), todo);
+        assert_eq!(read(&dir, DONE_TXT_FILENAME), done);
+
+        let [call, water, rent] = &tasks[..] else {
+            panic!("expected three tasks");
+        };
+        assert!(call.state == TaskState::Chosen && !call.done);
+        assert_eq!(
+            call.task_text,
+            "call mom +family @phone due:2026-02-01 foo:bar"
+        );
+        assert!(water.state == TaskState::Uncertain);
+        assert_eq!(water.task_text, "water plants rec:1w");
+        assert!(rent.done && rent.state == TaskState::Chosen);
+        assert_eq!(
+            rent.modified.done.date_naive(),
+            parse_date("2026-03-04").unwrap()
+        );
+        assert_eq!(rent.task_text, "pay rent +home t:2026-03-01");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn completing_and_reopening_keeps_priority_dates_and_extensions() {
+        let line =
+            format!("(B) 2026-01-02 call mom +family @phone due:2026-02-01 foo:bar id:{CALL}\n");
+        let (mut store, dir) = todo_txt(&line, "");
+        let mut tasks = store.load_tasks().unwrap();
+
+        tasks[0].done = true;
+        tasks[0].modified.done = parse_date("2026-01-20")
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_utc();
+        store.save_tasks(&tasks).unwrap();
+        assert_eq!(
+            read(&dir, TODO_TXT_FILENAME),
+            format!(
+                "x 2026-01-20 2026-01-02 call mom +family @phone due:2026-02-01 foo:bar pri:B id:{CALL}\n"
+            )
+        );
+
+        tasks[0].done = false;
+        store.save_tasks(&tasks).unwrap();
+        assert_eq!(read(&dir, TODO_TXT_FILENAME), line);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lines_without_an_id_keep_it_once_saved() {
+        let (mut store, dir) = todo_txt("(A) water plants @home rec:1w\n", "");
+        let tasks = store.load_tasks().unwrap();
+        store.save_tasks(&tasks).unwrap();
+
+        let id = tasks[0].task_id;
+        let expected = format!("(A) water plants @home rec:1w id:{id}\n");
+        assert_eq!(read(&dir, TODO_TXT_FILENAME), expected);
+        assert_eq!(store.load_tasks().unwrap()[0].task_id, id);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn exported_tasks_import_unchanged() {
+        let mut tasks: Vec<Task> = [
+            ("call mom +family @phone due:2026-02-01", TaskState::Chosen),
+            ("water plants foo:bar", TaskState::Uncertain),
+            ("pay rent +home", TaskState::Normal),
+            ("file taxes", TaskState::Chosen),
+            ("x out the plan", TaskState::Normal),
+            ("(B) call mum", TaskState::Normal),
+            ("2026-01-01 meeting", TaskState::Normal),
+            ("2026-01-02 review", TaskState::Chosen),
+            ("2026-01-03 retro", TaskState::Normal),
+        ]
+        .into_iter()
+        .map(|(text, state)| Task::new(text.to_string(), state))
+        .collect();
+        tasks[3].done = true;
+        tasks[8].done = true;
+
+        let imported = import(&export(&tasks));
+        assert_eq!(imported.len(), tasks.len());
+        for (imported, task) in imported.iter().zip(&tasks) {
+            assert_eq!(imported.task_id, task.task_id);
+            assert_eq!(imported.task_text, task.task_text);
+            assert_eq!(imported.done, task.done);
+            assert!(imported.state == task.state);
+        }
+    }
+}